@@ -7,6 +7,8 @@ use crate::change::{SdpAnswer, SdpOffer};
 use crate::crypto::KeyingMaterial;
 use crate::crypto::SrtpProfile;
 use crate::format::Codec;
+use crate::ice_::IceAgent;
+use crate::io::StunMessage;
 use crate::packet::{DepacketizingBuffer, RtpMeta};
 use crate::rtp_::{Frequency, MediaTime, RtpHeader};
 use crate::streams::register::ReceiverRegister;
@@ -147,3 +149,13 @@ pub fn receive_register(data: &[u8]) -> Option<()> {
         }
     }
 }
+
+pub fn stun_message(data: &[u8]) -> Option<()> {
+    let message = StunMessage::parse(data).ok()?;
+    let _ = message.check_integrity("pass");
+
+    let agent = IceAgent::new();
+    agent.accepts_message(&message);
+
+    Some(())
+}
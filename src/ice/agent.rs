@@ -38,10 +38,19 @@ pub struct IceAgent {
     /// process itself.
     ice_lite: bool,
 
+    /// Whether connectivity checks are paused.
+    paused: bool,
+
+    /// Which kinds of local candidates to use.
+    transport_policy: IceTransportPolicy,
+
     // The default limit of candidate pairs for the checklist set is 100,
     // but the value MUST be configurable.
     max_candidate_pairs: Option<usize>,
 
+    /// Maximum number of (not discarded) remote candidates.
+    max_remote_candidates: Option<usize>,
+
     /// Credentials for this side. Set on init and ice-restart.
     local_credentials: IceCreds,
 
@@ -91,6 +100,9 @@ pub struct IceAgent {
     /// Statistics counter for the agent.
     stats: IceAgentStats,
 
+    /// Timing of the connection establishment.
+    timings: IceTimings,
+
     /// The timing configuration for STUN bindings.
     timing_config: StunTiming,
 }
@@ -107,6 +119,17 @@ struct StunRequest {
     remote_ufrag: String,
 }
 
+/// Outcome of checking max_remote_candidates before adding a remote candidate.
+#[derive(Debug)]
+enum RemoteRoom {
+    /// There is room without discarding any candidate.
+    Free,
+    /// The candidate at this index was discarded to make room.
+    Evicted(usize),
+    /// No room, the new candidate must not be added.
+    Full,
+}
+
 const REMOTE_PEER_REFLEXIVE_TEMP_FOUNDATION: &str = "tmp_prflx";
 
 /// States the ICE connection can be in.
@@ -163,6 +186,45 @@ impl IceConnectionState {
     pub fn is_disconnected(&self) -> bool {
         *self == IceConnectionState::Disconnected
     }
+
+    /// Tells if this state is still working towards a first connection.
+    pub fn is_connecting(&self) -> bool {
+        use IceConnectionState::*;
+        matches!(self, New | Checking)
+    }
+}
+
+/// Policy for which kinds of local candidates an [`IceAgent`] uses.
+///
+/// This corresponds to `RTCIceTransportPolicy`. It only applies to local
+/// candidates, which in turn decides which pairs can be formed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IceTransportPolicy {
+    /// Use all kinds of candidates.
+    #[default]
+    All,
+
+    /// Use all but host candidates, to not reveal local IP addresses.
+    ///
+    /// This also redacts the related address (`raddr`/`rport`) of local candidates,
+    /// which for a server reflexive candidate is its local base.
+    NoHost,
+
+    /// Only use relayed (TURN) candidates.
+    ///
+    /// Like [`IceTransportPolicy::NoHost`], this redacts the related address of
+    /// local candidates.
+    RelayOnly,
+}
+
+impl IceTransportPolicy {
+    fn allows(&self, kind: CandidateKind) -> bool {
+        match self {
+            IceTransportPolicy::All => true,
+            IceTransportPolicy::NoHost => kind != CandidateKind::Host,
+            IceTransportPolicy::RelayOnly => kind == CandidateKind::Relayed,
+        }
+    }
 }
 
 /// Credentials for STUN packages.
@@ -176,13 +238,65 @@ pub struct IceCreds {
     pub pass: String,
 }
 
+/// Counters for an [`IceAgent`].
+///
+/// Resets on ICE restart.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct IceAgentStats {
+    /// Number of STUN binding requests sent, including retransmits.
     pub bind_request_sent: u64,
+    /// Number of the sent binding requests that were retransmits of an unanswered request.
+    pub bind_request_retransmit: u64,
+    /// Number of successful binding responses received.
     pub bind_success_recv: u64,
+    /// Number of binding requests received.
     pub bind_request_recv: u64,
+    /// Number of peer reflexive remote candidates discovered from binding requests.
     pub discovered_recv_count: u64,
+    /// Number of [`IceAgentEvent::NominatedSend`] emitted.
     pub nomination_send_count: u64,
+    /// Current number of local candidates, not counting invalidated ones.
+    pub local_candidates: usize,
+    /// Current number of remote candidates, not counting invalidated ones.
+    pub remote_candidates: usize,
+    /// Current number of candidate pairs.
+    pub candidate_pairs: usize,
+    /// Current number of candidate pairs that have not been checked yet.
+    pub pairs_waiting: usize,
+    /// Current number of candidate pairs with a check in progress.
+    pub pairs_in_progress: usize,
+    /// Current number of candidate pairs with a successful check.
+    pub pairs_succeeded: usize,
+}
+
+/// Timing of the ICE connection establishment for an [`IceAgent`].
+///
+/// Resets on ICE restart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IceTimings {
+    /// When the first STUN binding request was sent.
+    ///
+    /// This is never set in ice-lite, since ice-lite does no checks.
+    pub first_check: Option<Instant>,
+
+    /// When the agent first reached [`IceConnectionState::Connected`] (or went straight
+    /// to [`IceConnectionState::Completed`]).
+    pub connected: Option<Instant>,
+
+    /// When the agent first reached [`IceConnectionState::Completed`].
+    pub completed: Option<Instant>,
+}
+
+impl IceTimings {
+    /// Time from the first check until the agent was connected.
+    pub fn time_to_connected(&self) -> Option<Duration> {
+        Some(self.connected? - self.first_check?)
+    }
+
+    /// Time from the first check until the agent was completed.
+    pub fn time_to_completed(&self) -> Option<Duration> {
+        Some(self.completed? - self.first_check?)
+    }
 }
 
 /// Events from an [`IceAgent`].
@@ -256,7 +370,10 @@ impl IceAgent {
         IceAgent {
             last_now: None,
             ice_lite: false,
+            paused: false,
+            transport_policy: IceTransportPolicy::default(),
             max_candidate_pairs: None,
+            max_remote_candidates: None,
             local_credentials,
             remote_credentials: None,
             controlling: false,
@@ -271,6 +388,7 @@ impl IceAgent {
             discovered_recv: HashSet::new(),
             nominated_send: None,
             stats: IceAgentStats::default(),
+            timings: IceTimings::default(),
             timing_advance: Duration::from_millis(50),
             timing_config: StunTiming::default(),
         }
@@ -283,6 +401,20 @@ impl IceAgent {
         self.max_candidate_pairs = Some(max);
     }
 
+    /// The maximum number of remote candidates to use.
+    ///
+    /// This protects against a peer flooding us with candidates. When the limit is
+    /// reached, a new remote candidate replaces the worst priority one, if the new
+    /// one has a better priority. Otherwise it is ignored.
+    ///
+    /// Candidates in a nominated or succeeded pair are never replaced. Peer reflexive
+    /// candidates, discovered from incoming STUN requests, are not counted.
+    ///
+    /// Default is no limit.
+    pub fn set_max_remote_candidates(&mut self, max: usize) {
+        self.max_remote_candidates = Some(max);
+    }
+
     /// Whether ice_lite is enabled.
     ///
     /// Default is disabled.
@@ -292,9 +424,58 @@ impl IceAgent {
 
     /// Enable or disable ice_lite.
     ///
+    /// An ice-lite agent is always controlled, enabling ice-lite also makes the
+    /// agent controlled.
+    ///
     /// Default is disabled.
     pub fn set_ice_lite(&mut self, enabled: bool) {
         self.ice_lite = enabled;
+        if enabled {
+            self.set_controlling(false);
+        }
+    }
+
+    /// Pause connectivity checks.
+    ///
+    /// No binding requests are sent and no candidate pairs are pruned while paused.
+    /// Incoming binding requests are still answered. Useful when an app is put in
+    /// the background.
+    pub fn pause(&mut self) {
+        if !self.paused {
+            debug!("Pause connectivity checks");
+            self.paused = true;
+        }
+    }
+
+    /// Resume connectivity checks after [`IceAgent::pause`].
+    ///
+    /// Checks that became due while paused are sent one at a time, paced like
+    /// any other checks.
+    pub fn resume(&mut self) {
+        if self.paused {
+            debug!("Resume connectivity checks");
+            self.paused = false;
+        }
+    }
+
+    /// Whether connectivity checks are paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Which kinds of local candidates the agent uses.
+    ///
+    /// Default is [`IceTransportPolicy::All`].
+    pub fn transport_policy(&self) -> IceTransportPolicy {
+        self.transport_policy
+    }
+
+    /// Set which kinds of local candidates the agent uses.
+    ///
+    /// This must be set before adding local candidates, since it is enforced
+    /// in [`IceAgent::add_local_candidate`].
+    pub fn set_transport_policy(&mut self, policy: IceTransportPolicy) {
+        self.transport_policy = policy;
     }
 
     /// Set a new timing advance (Ta) value.
@@ -348,6 +529,16 @@ impl IceAgent {
         self.timing_config.max_retransmits = num;
     }
 
+    /// Randomize the STUN retransmit and keepalive intervals by ±20%.
+    ///
+    /// On a server with many peers, agents started at the same time would otherwise send
+    /// their checks in synchronized bursts.
+    ///
+    /// Defaults to disabled.
+    pub fn set_stun_jitter(&mut self, enabled: bool) {
+        self.timing_config.jitter = enabled;
+    }
+
     /// How long we at most tolerate missing replies for a candidate pair before considering it failed.
     pub fn ice_timeout(&self) -> Duration {
         self.timing_config.timeout()
@@ -374,6 +565,19 @@ impl IceAgent {
             .any(|pair| self.remote_candidates[pair.remote_idx()].addr() == addr)
     }
 
+    /// Whether the socket of the local candidate is used by any candidate pair.
+    ///
+    /// Pairs are formed from the base of a local candidate, so a server reflexive
+    /// candidate is in use as long as a pair uses the same base and protocol.
+    /// Pairs that fail are removed, which means a candidate that is not in use can
+    /// have its socket closed.
+    pub fn is_candidate_in_use(&self, c: &Candidate) -> bool {
+        self.candidate_pairs.iter().any(|pair| {
+            let local = pair.local_candidate(&self.local_candidates);
+            local.base() == c.base() && local.proto() == c.proto()
+        })
+    }
+
     /// Sets the remote ice credentials.
     pub fn set_remote_credentials(&mut self, r: IceCreds) {
         if self.remote_credentials.as_ref() != Some(&r) {
@@ -382,19 +586,29 @@ impl IceAgent {
         }
     }
 
+    /// Whether the remote ice credentials are set.
+    ///
+    /// Without them, no binding requests are sent and incoming requests are queued.
+    pub fn has_remote_credentials(&self) -> bool {
+        self.remote_credentials.is_some()
+    }
+
+    /// Whether the agent has what it needs to do connectivity checks.
+    ///
+    /// That is remote credentials and at least one candidate pair. It is always
+    /// safe to call [`IceAgent::handle_timeout`], this is only informational.
+    pub fn is_ready_to_check(&self) -> bool {
+        self.has_remote_credentials() && !self.candidate_pairs.is_empty()
+    }
+
     /// Determine whether an equivalent remote candidate is part of a viable candidate pair.
     pub fn find_pair_for_equivalent_remote_candidate(
         &self,
         c: &Candidate,
     ) -> Option<&CandidatePair> {
-        self.candidate_pairs.iter().find(|pair| {
-            let o = &self.remote_candidates[pair.remote_idx()];
-            c.addr() == o.addr()
-                && c.base() == o.base()
-                && c.proto() == o.proto()
-                && c.kind() == o.kind()
-                && c.raddr() == o.raddr()
-        })
+        self.candidate_pairs
+            .iter()
+            .find(|pair| self.remote_candidates[pair.remote_idx()].is_equivalent(c))
     }
 
     /// Credentials for STUN.
@@ -408,19 +622,14 @@ impl IceAgent {
     /// The responses utilize the same usernames and passwords as the requests
     /// (note that the USERNAME attribute is not present in the response).
     ///
-    /// ### Panics
-    ///
-    /// Panics if there are no remote credentials set.
-    fn stun_credentials(&self, reply: bool) -> (String, String) {
+    /// Returns `None` for requests if there are no remote credentials set.
+    fn stun_credentials(&self, reply: bool) -> Option<(String, String)> {
         let local = &self.local_credentials;
 
         let (left, right, peer_pass) = if reply {
             ("not_used", "not_used", "not_used")
         } else {
-            let peer = self
-                .remote_credentials
-                .as_ref()
-                .expect("Remote ICE credentials");
+            let peer = self.remote_credentials.as_ref()?;
             (&peer.ufrag[..], &local.ufrag[..], &peer.pass[..])
         };
 
@@ -431,7 +640,7 @@ impl IceAgent {
             peer_pass.into()
         };
 
-        (username, password)
+        Some((username, password))
     }
 
     /// Whether this side is controlling or controlled.
@@ -442,10 +651,41 @@ impl IceAgent {
 
     /// Set whether we are the controlling side.
     ///
-    /// You should not call this function after ICE candidate pair formation
-    /// has started, as the controlling state influences candidate prio!
+    /// The controlling state influences candidate pair prio. Changing it after pair
+    /// formation recalculates the prio of all pairs.
+    ///
+    /// An ice-lite agent is never controlling, and setting `true` is ignored.
     pub fn set_controlling(&mut self, v: bool) {
+        if v && self.ice_lite {
+            debug!("Ignore set_controlling(true) due to ice-lite mode");
+            return;
+        }
+        if self.controlling == v {
+            return;
+        }
         self.controlling = v;
+        self.recalculate_pair_prios();
+    }
+
+    /// Recalculate the prio of all candidate pairs after a role change.
+    fn recalculate_pair_prios(&mut self) {
+        if self.candidate_pairs.is_empty() {
+            return;
+        }
+
+        debug!(
+            "Recalculate pair prios for controlling: {}",
+            self.controlling
+        );
+
+        for pair in &mut self.candidate_pairs {
+            let local = pair.local_candidate(&self.local_candidates);
+            let remote = pair.remote_candidate(&self.remote_candidates);
+            let prio = CandidatePair::calculate_prio(self.controlling, remote.prio(), local.prio());
+            pair.set_prio(prio);
+        }
+
+        self.candidate_pairs.sort();
     }
 
     /// Current ice agent state.
@@ -453,12 +693,97 @@ impl IceAgent {
         self.state
     }
 
+    /// The id of the pair currently nominated for sending.
+    ///
+    /// This is the pair of the last [`IceAgentEvent::NominatedSend`], and is `None`
+    /// before the first nomination and after an ICE restart.
+    pub fn nominated_send_id(&self) -> Option<PairId> {
+        self.nominated_send
+    }
+
+    /// Human readable dump of all candidate pairs, best first.
+    ///
+    /// This is for diagnostics, such as attaching to a bug report. The format is
+    /// not stable. The currently nominated pair is marked with `*`, and the base of
+    /// a local candidate is shown when it differs from the candidate address.
+    pub fn dump_checklist(&self) -> String {
+        use std::fmt::Write;
+
+        let mut s = String::new();
+
+        for pair in &self.candidate_pairs {
+            let local = pair.local_candidate(&self.local_candidates);
+            let remote = pair.remote_candidate(&self.remote_candidates);
+            let selected = if self.nominated_send == Some(pair.id()) {
+                '*'
+            } else {
+                ' '
+            };
+
+            let base = if local.base() != local.addr() {
+                format!(" base={}", local.base())
+            } else {
+                String::new()
+            };
+
+            // Writing to a String can't fail.
+            let _ = writeln!(
+                s,
+                "{} {} {} {}{} -> {} {} prio={} state={:?} nomination={:?} attempts={} rtt={:?} id={}",
+                selected,
+                local.proto(),
+                local.kind(),
+                local.addr(),
+                base,
+                remote.kind(),
+                remote.addr(),
+                pair.prio(),
+                pair.state(),
+                pair.nomination_state(),
+                pair.binding_attempt_count(),
+                pair.last_rtt(),
+                pair.id(),
+            );
+        }
+
+        s
+    }
+
     /// Stats for the agent.
     ///
     /// Resets on ICE restart.
-    #[allow(unused)]
     pub fn stats(&self) -> IceAgentStats {
-        self.stats
+        IceAgentStats {
+            local_candidates: self
+                .local_candidates
+                .iter()
+                .filter(|c| !c.discarded())
+                .count(),
+            remote_candidates: self
+                .remote_candidates
+                .iter()
+                .filter(|c| !c.discarded())
+                .count(),
+            candidate_pairs: self.candidate_pairs.len(),
+            pairs_waiting: self.count_pairs(CheckState::Waiting),
+            pairs_in_progress: self.count_pairs(CheckState::InProgress),
+            pairs_succeeded: self.count_pairs(CheckState::Succeeded),
+            ..self.stats
+        }
+    }
+
+    fn count_pairs(&self, state: CheckState) -> usize {
+        self.candidate_pairs
+            .iter()
+            .filter(|p| p.state() == state)
+            .count()
+    }
+
+    /// Timing of the connection establishment.
+    ///
+    /// Resets on ICE restart.
+    pub fn timings(&self) -> IceTimings {
+        self.timings
     }
 
     /// Adds a local candidate.
@@ -477,6 +802,20 @@ impl IceAgent {
             }
         }
 
+        if !self.transport_policy.allows(c.kind()) {
+            debug!(
+                "Reject candidate due to transport policy {:?}: {:?}",
+                self.transport_policy, c
+            );
+            return false;
+        }
+
+        // Browsers signal `raddr 0.0.0.0 rport 0` when hiding host candidates, since
+        // the related address of a server reflexive candidate is the local base.
+        if self.transport_policy != IceTransportPolicy::All {
+            c.redact_raddr();
+        }
+
         // "Adopt" any incoming candidate by setting our current ufrag.
         c.set_ufrag(&self.local_credentials.ufrag);
 
@@ -609,17 +948,127 @@ impl IceAgent {
         true
     }
 
+    /// Adds host candidates for a list of local interface addresses.
+    ///
+    /// Within each address family, the addresses should be ordered by preference, the
+    /// first being the most preferred. The families are interleaved like for
+    /// [`IceAgent::add_local_candidate`], which means the first IPv6 address is preferred
+    /// over the first IPv4 address, which is preferred over the second IPv6 address
+    /// and so on.
+    ///
+    /// Loopback addresses are skipped unless `allow_loopback` is set. Addresses
+    /// [`Candidate::host`] rejects (such as link-local or unspecified) are always skipped.
+    ///
+    /// Returns the number of candidates added.
+    pub fn add_host_candidates(
+        &mut self,
+        addrs: impl IntoIterator<Item = SocketAddr>,
+        proto: Protocol,
+        allow_loopback: bool,
+    ) -> usize {
+        let mut added = 0;
+
+        for addr in addrs {
+            if addr.ip().is_loopback() && !allow_loopback {
+                debug!("Skip loopback host candidate: {}", addr);
+                continue;
+            }
+
+            let c = match Candidate::host(addr, proto) {
+                Ok(c) => c,
+                Err(e) => {
+                    debug!("Skip host candidate {}: {}", addr, e);
+                    continue;
+                }
+            };
+
+            if self.add_local_candidate(c) {
+                added += 1;
+            }
+        }
+
+        added
+    }
+
     /// Adds a remote candidate.
     ///
     /// Returns `false` if the candidate was not added because it is redundant.
     /// Adding loopback addresses or multicast/broadcast addresses causes
     /// an error.
-    pub fn add_remote_candidate(&mut self, mut c: Candidate) {
+    pub fn add_remote_candidate(&mut self, c: Candidate) {
+        let Some(remote_idx) = self.do_add_remote_candidate(c) else {
+            return;
+        };
+
+        let ipv4 = self.remote_candidates[remote_idx].addr().is_ipv4();
+
+        // These are the indexes of the local candidates this candidate should be paired with.
+        let local_idxs: Vec<_> = self
+            .local_candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| !v.discarded() && v.addr().is_ipv4() == ipv4)
+            .map(|(i, _)| i)
+            .collect();
+
+        let remote_idxs = [remote_idx];
+        self.form_pairs(&local_idxs, &remote_idxs);
+    }
+
+    /// Adds several remote candidates at once.
+    ///
+    /// This is the same as calling [`IceAgent::add_remote_candidate`] for each candidate,
+    /// but the pairs are formed (and sorted) once for all of them. Useful when all the
+    /// remote candidates arrive together in a full SDP.
+    pub fn add_remote_candidates(&mut self, cs: impl IntoIterator<Item = Candidate>) {
+        let mut remote_idxs: Vec<usize> = vec![];
+
+        for c in cs {
+            // Pairs are not formed until the end, which means find_pair_for_equivalent_remote_candidate
+            // can't see duplicates within the batch.
+            let duplicate = remote_idxs
+                .iter()
+                .any(|i| self.remote_candidates[*i].is_equivalent(&c));
+            if duplicate {
+                trace!("Ignoring candidate({c:?}) that matches another candidate in the batch");
+                continue;
+            }
+
+            if let Some(idx) = self.do_add_remote_candidate(c) {
+                if !remote_idxs.contains(&idx) {
+                    remote_idxs.push(idx);
+                }
+            }
+        }
+
+        // A candidate early in the batch can be discarded by a later one when
+        // we are at max_remote_candidates.
+        remote_idxs.retain(|i| !self.remote_candidates[*i].discarded());
+
+        if remote_idxs.is_empty() {
+            return;
+        }
+
+        let local_idxs: Vec<_> = self
+            .local_candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| !v.discarded())
+            .map(|(i, _)| i)
+            .collect();
+
+        self.form_pairs(&local_idxs, &remote_idxs);
+    }
+
+    /// Adds the remote candidate to the list, without forming any pairs.
+    ///
+    /// Returns the index of the candidate, or `None` if it was rejected or ignored.
+    fn do_add_remote_candidate(&mut self, mut c: Candidate) -> Option<usize> {
         // This is a a:rtcp-mux-only implementation. The only component
         // we accept is 1 for RTP.
         if c.component_id() != 1 {
             debug!("Reject candidate for component other than 1: {:?}", c);
-            return;
+            return None;
         }
 
         if let Some(creds) = &self.remote_credentials {
@@ -629,7 +1078,7 @@ impl IceAgent {
                         "Reject candidate with ufrag mismatch: {} != {}",
                         ufrag, creds.ufrag
                     );
-                    return;
+                    return None;
                 }
             }
         }
@@ -647,7 +1096,7 @@ impl IceAgent {
                 // Existing non-discarded candidate in viable pair, ignore
                 // Discarded candidates and candidates not in a viable pair are handled below
                 trace!("Ignoring candidate({c:?}) that exactly matches existing non-discarded candidate");
-                return;
+                return None;
             }
             Some((i, o)) if o.discarded() => Some(i),
             _ => None,
@@ -663,11 +1112,16 @@ impl IceAgent {
                     && v.addr() == c.addr()
             });
 
-        let ipv4 = c.addr().is_ipv4();
+        let remote_idx = if let Some((idx, _)) = existing_prflx {
+            // The peer reflexive candidate is not counted towards max_remote_candidates,
+            // but the signalled candidate replacing it might be.
+            if let RemoteRoom::Full = self.make_room_for_remote_candidate(&c) {
+                return None;
+            }
 
-        let remote_idx = if let Some((idx, existing)) = existing_prflx {
             // If any subsequent candidate exchanges contain this peer-reflexive
             // candidate, it will signal the actual foundation for the candidate.
+            let existing = &mut self.remote_candidates[idx];
             info!(
                 "Replace peer reflexive candidate, current: {:?} replaced with: {:?}",
                 existing, c
@@ -681,28 +1135,92 @@ impl IceAgent {
                 o.discarded().then_some((idx, o))
             });
 
-            if let Some((idx, other)) = existing_discarded {
+            if let Some((idx, _)) = existing_discarded {
+                if let RemoteRoom::Full = self.make_room_for_remote_candidate(&c) {
+                    return None;
+                }
+
+                let other = &mut self.remote_candidates[idx];
                 debug!("Re-enable previously discarded remote: {:?}", other);
                 other.set_discarded(false);
                 idx
             } else {
-                info!("Add remote candidate: {:?}", c);
-                self.remote_candidates.push(c);
-                self.remote_candidates.len() - 1
+                match self.make_room_for_remote_candidate(&c) {
+                    RemoteRoom::Free => {
+                        info!("Add remote candidate: {:?}", c);
+                        self.remote_candidates.push(c);
+                        self.remote_candidates.len() - 1
+                    }
+                    RemoteRoom::Evicted(idx) => {
+                        // Reuse the slot, or a peer flooding us with ever better
+                        // candidates would grow the list without bound.
+                        info!("Add remote candidate in place of discarded: {:?}", c);
+                        self.remote_candidates[idx] = c;
+                        idx
+                    }
+                    RemoteRoom::Full => return None,
+                }
             }
         };
 
-        // These are the indexes of the local candidates this candidate should be paired with.
-        let local_idxs: Vec<_> = self
-            .local_candidates
+        Some(remote_idx)
+    }
+
+    /// Checks max_remote_candidates before adding a new remote candidate.
+    ///
+    /// Discards the worst priority remote candidate, and the pairs using it, if the
+    /// new one is better.
+    fn make_room_for_remote_candidate(&mut self, c: &Candidate) -> RemoteRoom {
+        let Some(max) = self.max_remote_candidates else {
+            return RemoteRoom::Free;
+        };
+
+        let counted = |v: &Candidate| !v.discarded() && v.kind() != CandidateKind::PeerReflexive;
+
+        if !counted(c) {
+            return RemoteRoom::Free;
+        }
+
+        let active = self.remote_candidates.iter().filter(|v| counted(v)).count();
+
+        if active < max {
+            return RemoteRoom::Free;
+        }
+
+        // Replacing a candidate we are using would break a working connection.
+        let in_use = |idx: usize| {
+            self.candidate_pairs.iter().any(|p| {
+                p.remote_idx() == idx && (p.is_nominated() || p.state() == CheckState::Succeeded)
+            })
+        };
+
+        let worst = self
+            .remote_candidates
             .iter()
             .enumerate()
-            .filter(|(_, v)| !v.discarded() && v.addr().is_ipv4() == ipv4)
-            .map(|(i, _)| i)
-            .collect();
+            .filter(|(i, v)| counted(v) && !in_use(*i))
+            .min_by_key(|(_, v)| v.prio())
+            .map(|(i, v)| (i, v.prio()));
 
-        let remote_idxs = [remote_idx];
-        self.form_pairs(&local_idxs, &remote_idxs);
+        match worst {
+            Some((idx, prio)) if prio < c.prio() => {
+                let worst = &mut self.remote_candidates[idx];
+                debug!(
+                    "Discard remote candidate due to max_remote_candidates ({}): {:?}",
+                    max, worst
+                );
+                worst.set_discarded(true);
+                self.discard_candidate_pairs_by_remote(idx);
+                RemoteRoom::Evicted(idx)
+            }
+            _ => {
+                debug!(
+                    "Reject remote candidate due to max_remote_candidates ({}): {:?}",
+                    max, c
+                );
+                RemoteRoom::Full
+            }
+        }
     }
 
     /// Form pairs given two slices of indexes into the local_candidates and remote_candidates.
@@ -712,14 +1230,18 @@ impl IceAgent {
                 let local = &self.local_candidates[*local_idx];
                 let remote = &self.remote_candidates[*remote_idx];
 
-                // Candidates in a pair must share the same protocol
-                if local.proto() != remote.proto() {
+                // Candidates in a pair must share the same protocol and address family.
+                if local.proto() != remote.proto()
+                    || local.addr().is_ipv4() != remote.addr().is_ipv4()
+                {
                     continue 'outer;
                 }
 
                 let prio =
                     CandidatePair::calculate_prio(self.controlling, remote.prio(), local.prio());
-                let mut pair = CandidatePair::new(*local_idx, *remote_idx, prio);
+                let id = PairId::new(local, remote);
+                let cost = local.network_cost().max(remote.network_cost());
+                let mut pair = CandidatePair::new(id, *local_idx, *remote_idx, prio, cost);
 
                 trace!("Form pair local: {:?} remote: {:?}", local, remote);
 
@@ -793,6 +1315,7 @@ impl IceAgent {
             let pair = self.candidate_pairs.pop();
             debug!("Remove overflow pair {:?}", pair);
         }
+        self.forget_removed_nominated_send();
     }
 
     /// Invalidate a candidate and remove it from the connection.
@@ -806,12 +1329,12 @@ impl IceAgent {
     /// Returns `true` if the candidate was found and invalidated.
     #[allow(unused)]
     pub fn invalidate_candidate(&mut self, c: &Candidate) -> bool {
-        if let Some((idx, other)) = self.local_candidates.iter_mut().enumerate().find(|(_, v)| {
-            v.addr() == c.addr()
-                && v.base() == c.base()
-                && v.raddr() == c.raddr()
-                && v.kind() == c.kind()
-        }) {
+        if let Some((idx, other)) = self
+            .local_candidates
+            .iter_mut()
+            .enumerate()
+            .find(|(_, v)| v.is_equivalent(c))
+        {
             if !other.discarded() {
                 info!("Local candidate to discard {:?}", other);
                 other.set_discarded(true);
@@ -824,12 +1347,7 @@ impl IceAgent {
             .remote_candidates
             .iter_mut()
             .enumerate()
-            .find(|(_, v)| {
-                v.addr() == c.addr()
-                    && v.base() == c.base()
-                    && v.raddr() == c.raddr()
-                    && v.kind() == c.kind()
-            })
+            .find(|(_, v)| v.is_equivalent(c))
         {
             if !other.discarded() {
                 info!("Remote candidate to discard {:?}", other);
@@ -861,9 +1379,12 @@ impl IceAgent {
         self.remote_credentials = None;
         self.remote_candidates.clear();
         self.candidate_pairs.clear();
+        self.nominated_send = None;
         self.transmit.clear();
         self.events.clear();
         self.discovered_recv.clear();
+        self.stats = IceAgentStats::default();
+        self.timings = IceTimings::default();
 
         if keep_local_candidates {
             // If we're keeping the candidates, we must update the ufrag to the new credentials.
@@ -885,12 +1406,26 @@ impl IceAgent {
     fn discard_candidate_pairs_by_local(&mut self, local_idx: usize) {
         trace!("Discard pairs for local candidate index: {:?}", local_idx);
         self.candidate_pairs.retain(|c| c.local_idx() != local_idx);
+        self.forget_removed_nominated_send();
     }
 
     /// Discard candidate pairs that contain the candidate identified by a remote index.
     fn discard_candidate_pairs_by_remote(&mut self, remote: usize) {
         trace!("Discard pairs for remote candidate index: {:?}", remote);
         self.candidate_pairs.retain(|c| c.remote_idx() != remote);
+        self.forget_removed_nominated_send();
+    }
+
+    /// Clears `nominated_send` if that pair is no longer in the list.
+    ///
+    /// The [`PairId`] is stable for a path, so a later pair for the same path must
+    /// not be mistaken for the one we already nominated.
+    fn forget_removed_nominated_send(&mut self) {
+        if let Some(id) = self.nominated_send {
+            if !self.candidate_pairs.iter().any(|p| p.id() == id) {
+                self.nominated_send = None;
+            }
+        }
     }
 
     /// Tells whether the message is for this agent instance.
@@ -903,7 +1438,10 @@ impl IceAgent {
         trace!("Check if accepts message: {:?}", message);
 
         let do_integrity_check = |is_request: bool| -> bool {
-            let (_, password) = self.stun_credentials(is_request);
+            let Some((_, password)) = self.stun_credentials(is_request) else {
+                trace!("Message rejected, no remote credentials");
+                return false;
+            };
             let integrity_passed = message.check_integrity(&password);
 
             // The integrity is always the last thing we check
@@ -922,8 +1460,12 @@ impl IceAgent {
                 // The username for the credential is formed by concatenating the
                 // username fragment provided by the peer with the username fragment of
                 // the ICE agent sending the request, separated by a colon (":").
-                // The existence of this username is checked in the STUN parser.
-                let (local, remote) = message.split_username().unwrap();
+                // The existence of this username is checked in the STUN parser for
+                // requests, but not for indications.
+                let Some((local, remote)) = message.split_username() else {
+                    trace!("Message rejected, missing or malformed username");
+                    return false;
+                };
 
                 let local_creds = self.local_credentials();
                 if local != local_creds.ufrag {
@@ -1044,7 +1586,9 @@ impl IceAgent {
         // prune failed candidates.
         let mut any_pruned = false;
         self.candidate_pairs.retain(|p| {
-            let keep = if self.ice_lite {
+            let keep = if self.paused {
+                true
+            } else if self.ice_lite {
                 p.has_recent_remote_binding_request(now)
             } else {
                 p.is_still_possible(now, &self.timing_config)
@@ -1056,6 +1600,7 @@ impl IceAgent {
             keep
         });
         if any_pruned {
+            self.forget_removed_nominated_send();
             self.evaluate_nomination();
             self.evaluate_state(now);
         }
@@ -1080,6 +1625,11 @@ impl IceAgent {
             return;
         }
 
+        if self.paused {
+            trace!("Stop timeout since checks are paused");
+            return;
+        }
+
         // when do we need to handle the next candidate pair?
         let next = self
             .candidate_pairs
@@ -1130,8 +1680,8 @@ impl IceAgent {
         }
 
         // when do we need to handle the next candidate pair?
-        let maybe_next = if self.ice_lite {
-            // ice-lite doesn't do checks.
+        let maybe_next = if self.ice_lite || self.paused {
+            // ice-lite doesn't do checks, and neither do we while paused.
             None
         } else {
             self.candidate_pairs
@@ -1170,6 +1720,15 @@ impl IceAgent {
         self.events.push_back(event);
     }
 
+    /// Tells if an incoming binding request claims the same ICE role as us.
+    fn is_role_conflict(&self, message: &StunMessage) -> bool {
+        if self.controlling {
+            message.ice_controlling().is_some()
+        } else {
+            message.ice_controlled().is_some()
+        }
+    }
+
     /// Return a pending [`IceAgentEvent`] from this agent.
     pub fn poll_event(&mut self) -> Option<IceAgentEvent> {
         let x = self.events.pop_front();
@@ -1193,6 +1752,20 @@ impl IceAgent {
 
         let trans_id = message.trans_id();
 
+        if self.is_role_conflict(message) {
+            // The roles follow from the SDP offer/answer, so a conflict is a peer
+            // bug. We don't resolve it by tie breaker (or answer 487), but carry on
+            // with our role. This is logged per request, hence not warn.
+            debug!(
+                "ICE role conflict, both sides are {}",
+                if self.controlling {
+                    "controlling"
+                } else {
+                    "controlled"
+                }
+            );
+        }
+
         // The existence of USERNAME is checked by the STUN parser.
         let (_, remote_ufrag) = message.split_username().unwrap();
 
@@ -1373,7 +1946,9 @@ impl IceAgent {
             // *  Its state is set to Waiting. (this is the default)
             // *  The pair is inserted into the checklist based on its priority.
             // *  The pair is enqueued into the triggered-check queue.
-            let pair = CandidatePair::new(local_idx, remote_idx, prio);
+            let id = PairId::new(local, remote);
+            let cost = local.network_cost().max(remote.network_cost());
+            let pair = CandidatePair::new(id, local_idx, remote_idx, prio, cost);
 
             debug!("Created new pair for STUN request: {:?}", pair);
 
@@ -1409,7 +1984,9 @@ impl IceAgent {
             self.evaluate_nomination();
         }
 
-        let (_, password) = self.stun_credentials(true);
+        let Some((_, password)) = self.stun_credentials(true) else {
+            return;
+        };
 
         let reply = StunMessage::reply(req.trans_id, req.source);
 
@@ -1438,7 +2015,10 @@ impl IceAgent {
     }
 
     fn stun_client_binding_request(&mut self, now: Instant, pair_idx: usize) {
-        let (username, password) = self.stun_credentials(false);
+        let Some((username, password)) = self.stun_credentials(false) else {
+            debug!("Can't send binding request without remote credentials");
+            return;
+        };
 
         let pair = &mut self.candidate_pairs[pair_idx];
         let local = pair.local_candidate(&self.local_candidates);
@@ -1446,10 +2026,15 @@ impl IceAgent {
         let prio = local.prio_prflx();
         // Only the controlling side sends USE-CANDIDATE.
         let use_candidate = self.controlling && pair.is_nominated();
+        let is_retransmit = pair.has_unanswered_attempt();
 
         let trans_id = pair.new_attempt(now, &self.timing_config);
 
         self.stats.bind_request_sent += 1;
+        if is_retransmit {
+            self.stats.bind_request_retransmit += 1;
+        }
+        self.timings.first_check.get_or_insert(now);
 
         let binding = StunMessage::binding_request(
             &username,
@@ -1600,14 +2185,15 @@ impl IceAgent {
             self.candidate_pairs
                 .iter_mut()
                 .filter(|p| p.state() == CheckState::Succeeded)
-                .max_by_key(|p| p.prio())
+                // Ord on pairs is best first, i.e. highest prio then lowest network cost.
+                .max_by(|a, b| b.cmp(a))
         } else {
             // For controlled agents, we pick the best pair from what the controlling
             // agent has indicated with USE-CANDIDATE stun attribute.
             self.candidate_pairs
                 .iter_mut()
                 .filter(|p| p.is_nominated())
-                .max_by_key(|p| p.prio())
+                .max_by(|a, b| b.cmp(a))
         };
 
         if let Some(best_prio) = best_prio {
@@ -1683,9 +2269,7 @@ impl IceAgent {
                 if any_nomination {
                     if self.ice_lite {
                         self.set_connection_state(Completed, "got nomination in ice lite");
-                        return;
-                    }
-                    if any_still_possible {
+                    } else if any_still_possible {
                         self.set_connection_state(Connected, "got nomination, still trying others");
                     } else {
                         self.set_connection_state(Completed, "got nomination, no others to try");
@@ -1717,6 +2301,13 @@ impl IceAgent {
                 }
             }
         }
+
+        if self.state.is_connected() {
+            self.timings.connected.get_or_insert(now);
+        }
+        if self.state == Completed {
+            self.timings.completed.get_or_insert(now);
+        }
     }
 
     pub(crate) fn remote_credentials(&self) -> Option<&IceCreds> {
@@ -1734,6 +2325,10 @@ mod test {
             self.candidate_pairs.len()
         }
 
+        fn pair_ids(&self) -> Vec<PairId> {
+            self.candidate_pairs.iter().map(|c| c.id()).collect()
+        }
+
         fn pair_indexes(&self) -> Vec<(usize, usize)> {
             self.candidate_pairs
                 .iter()
@@ -1761,6 +2356,25 @@ mod test {
         "[1002::]:5000".parse().unwrap()
     }
 
+    #[test]
+    fn connection_state_predicates() {
+        use IceConnectionState::*;
+
+        for state in [New, Checking, Connected, Completed, Disconnected] {
+            let expected = match state {
+                New | Checking => (true, false, false),
+                Connected | Completed => (false, true, false),
+                Disconnected => (false, false, true),
+            };
+            let actual = (
+                state.is_connecting(),
+                state.is_connected(),
+                state.is_disconnected(),
+            );
+            assert_eq!(actual, expected, "{state:?}");
+        }
+    }
+
     #[test]
     fn local_preference_host() {
         let mut agent = IceAgent::new();
@@ -1779,6 +2393,111 @@ mod test {
         assert_eq!(v, vec![65534, 65535, 65533, 65532]);
     }
 
+    #[test]
+    fn add_host_candidates_filters_and_orders() {
+        let mut agent = IceAgent::new();
+
+        let addrs = [
+            ipv4_1(),
+            "127.0.0.1:5000".parse().unwrap(),
+            ipv6_1(),
+            "169.254.1.1:5000".parse().unwrap(),
+            "0.0.0.0:5000".parse().unwrap(),
+            ipv4_2(),
+            "[::1]:5000".parse().unwrap(),
+            // Duplicate
+            ipv4_1(),
+        ];
+
+        let added = agent.add_host_candidates(addrs, Protocol::Udp, false);
+        assert_eq!(added, 3);
+
+        let v: Vec<_> = agent
+            .local_candidates
+            .iter()
+            .map(|c| (c.addr(), c.kind(), c.prio() >> 24, c.local_preference()))
+            .collect();
+
+        assert_eq!(
+            v,
+            vec![
+                (ipv4_1(), CandidateKind::Host, 126, 65534),
+                (ipv6_1(), CandidateKind::Host, 126, 65535),
+                (ipv4_2(), CandidateKind::Host, 126, 65532),
+            ]
+        );
+
+        let mut agent = IceAgent::new();
+        let added = agent.add_host_candidates(addrs, Protocol::Udp, true);
+        assert_eq!(added, 5);
+        assert!(agent
+            .local_candidates
+            .iter()
+            .any(|c| c.addr() == "127.0.0.1:5000".parse().unwrap()));
+        assert!(agent
+            .local_candidates
+            .iter()
+            .any(|c| c.addr() == "[::1]:5000".parse().unwrap()));
+    }
+
+    #[test]
+    fn transport_policy_filters_local_candidates() {
+        let kinds_for = |policy: IceTransportPolicy| {
+            let mut agent = IceAgent::new();
+            agent.set_transport_policy(policy);
+
+            agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+            agent.add_local_candidate(
+                Candidate::server_reflexive(ipv4_2(), "5.6.7.8:5000".parse().unwrap(), "udp")
+                    .unwrap(),
+            );
+            agent.add_local_candidate(Candidate::relayed(ipv4_3(), "udp").unwrap());
+            agent.add_remote_candidate(Candidate::host(ipv4_4(), "udp").unwrap());
+
+            let local: Vec<_> = agent.local_candidates.iter().map(|c| c.kind()).collect();
+            let pairs: Vec<_> = agent
+                .candidate_pairs
+                .iter()
+                .map(|p| p.local_candidate(&agent.local_candidates).kind())
+                .collect();
+
+            // Every local candidate forms a pair with the remote one.
+            assert_eq!(local.len(), pairs.len());
+
+            local
+        };
+
+        use CandidateKind::*;
+        assert_eq!(
+            kinds_for(IceTransportPolicy::All),
+            [Host, ServerReflexive, Relayed]
+        );
+        assert_eq!(
+            kinds_for(IceTransportPolicy::NoHost),
+            [ServerReflexive, Relayed]
+        );
+        assert_eq!(kinds_for(IceTransportPolicy::RelayOnly), [Relayed]);
+    }
+
+    #[test]
+    fn transport_policy_no_host_redacts_raddr() {
+        let srflx_sdp = |policy: IceTransportPolicy| {
+            let mut agent = IceAgent::new();
+            agent.set_transport_policy(policy);
+            agent.add_local_candidate(
+                Candidate::server_reflexive(ipv4_2(), "10.0.0.1:5000".parse().unwrap(), "udp")
+                    .unwrap(),
+            );
+            agent.local_candidates[0].to_sdp_string()
+        };
+
+        assert!(srflx_sdp(IceTransportPolicy::All).contains(" raddr 10.0.0.1 rport 5000"));
+
+        let sdp = srflx_sdp(IceTransportPolicy::NoHost);
+        assert!(sdp.contains(" typ srflx raddr 0.0.0.0 rport 0"));
+        assert!(!sdp.contains("10.0.0.1"));
+    }
+
     #[test]
     fn discard_adding_redundant() {
         let mut agent = IceAgent::new();
@@ -1969,6 +2688,560 @@ mod test {
         assert_eq!(pair.remote_binding_request_time, Some(now));
     }
 
+    #[test]
+    fn form_pairs_sorted_by_priority() {
+        let mut agent = IceAgent::new();
+        agent.set_controlling(true);
+
+        // local 0, local 1
+        agent.add_local_candidate(Candidate::with_priority(ipv4_1(), 100));
+        agent.add_local_candidate(Candidate::with_priority(ipv4_2(), 300));
+
+        // remote 0, remote 1
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_3(), 200));
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_4(), 50));
+
+        // pair priority = 2^32*MIN(G,D) + 2*MAX(G,D) + (G>D?1:0)
+        // (1, 0) - min 200
+        // (0, 0) - min 100
+        // (1, 1) - min 50, max 300
+        // (0, 1) - min 50, max 100
+        assert_eq!(agent.pair_indexes(), [(1, 0), (0, 0), (1, 1), (0, 1)]);
+
+        let prios: Vec<_> = agent.candidate_pairs.iter().map(|p| p.prio()).collect();
+        assert_eq!(
+            prios,
+            [
+                (1 << 32) * 200 + 2 * 300 + 1,
+                (1 << 32) * 100 + 2 * 200,
+                (1 << 32) * 50 + 2 * 300 + 1,
+                (1 << 32) * 50 + 2 * 100 + 1,
+            ]
+        );
+    }
+
+    #[test]
+    fn form_pairs_priority_tie_breaks_on_role() {
+        let prio_for = |controlling: bool| {
+            let mut agent = IceAgent::new();
+            agent.set_controlling(controlling);
+            agent.add_local_candidate(Candidate::with_priority(ipv4_1(), 100));
+            agent.add_remote_candidate(Candidate::with_priority(ipv4_3(), 200));
+            agent.candidate_pairs[0].prio()
+        };
+
+        // Controlling, G is the local 100 and D the remote 200: G>D is false.
+        assert_eq!(prio_for(true), (1 << 32) * 100 + 2 * 200);
+
+        // Controlled, G is the remote 200 and D the local 100: G>D is true.
+        assert_eq!(prio_for(false), (1 << 32) * 100 + 2 * 200 + 1);
+    }
+
+    #[test]
+    fn set_controlling_recalculates_pair_prios() {
+        let mut agent = IceAgent::new();
+        agent.set_controlling(true);
+        agent.add_local_candidate(Candidate::with_priority(ipv4_1(), 100));
+        agent.add_local_candidate(Candidate::with_priority(ipv4_2(), 300));
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_3(), 200));
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_4(), 400));
+
+        let prios = |agent: &IceAgent| -> Vec<_> {
+            agent
+                .candidate_pairs
+                .iter()
+                .map(|p| (p.local_idx(), p.remote_idx(), p.prio()))
+                .collect()
+        };
+        let fresh = |controlling: bool| -> Vec<_> {
+            let mut expected: Vec<_> = agent
+                .candidate_pairs
+                .iter()
+                .map(|p| {
+                    let l = p.local_candidate(&agent.local_candidates).prio();
+                    let r = p.remote_candidate(&agent.remote_candidates).prio();
+                    let prio = CandidatePair::calculate_prio(controlling, r, l);
+                    (p.local_idx(), p.remote_idx(), prio)
+                })
+                .collect();
+            expected.sort_by_key(|p| std::cmp::Reverse(p.2));
+            expected
+        };
+
+        let controlling = fresh(true);
+        let controlled = fresh(false);
+        assert_ne!(controlling, controlled);
+        assert_eq!(prios(&agent), controlling);
+
+        agent.set_controlling(false);
+        assert_eq!(prios(&agent), controlled);
+
+        agent.set_controlling(true);
+        assert_eq!(prios(&agent), controlling);
+    }
+
+    #[test]
+    fn form_pairs_equal_priority_prefers_low_network_cost() {
+        let mut agent = IceAgent::new();
+        agent.set_controlling(true);
+
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        let cellular = Candidate::from_sdp_string(
+            "candidate:1 1 udp 2130706175 3.4.5.6 5000 typ host network-cost 900",
+        )
+        .unwrap();
+        let wifi = Candidate::from_sdp_string(
+            "candidate:2 1 udp 2130706175 4.5.6.7 5000 typ host network-cost 10",
+        )
+        .unwrap();
+        agent.add_remote_candidate(cellular);
+        agent.add_remote_candidate(wifi);
+
+        let pairs = &agent.candidate_pairs;
+        assert_eq!(pairs[0].prio(), pairs[1].prio());
+        assert_eq!(agent.pair_indexes(), [(0, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn max_remote_candidates_keeps_best_prio() {
+        let flood = || {
+            (0..200_u32).map(|i| {
+                let addr = SocketAddr::new([10, 0, (i / 256) as u8, (i % 256) as u8].into(), 5000);
+                // Spread the priorities so the best ones arrive in the middle.
+                Candidate::with_priority(addr, 1000 + (i * 37) % 200)
+            })
+        };
+
+        let mut expected: Vec<_> = flood().map(|c| c.prio()).collect();
+        expected.sort_unstable_by(|a, b| b.cmp(a));
+        expected.truncate(20);
+
+        let retained = |agent: &IceAgent| {
+            let mut prios: Vec<_> = agent
+                .remote_candidates
+                .iter()
+                .filter(|c| !c.discarded())
+                .map(|c| c.prio())
+                .collect();
+            prios.sort_unstable_by(|a, b| b.cmp(a));
+            prios
+        };
+
+        // No limit by default.
+        let mut agent = IceAgent::new();
+        agent.add_remote_candidates(flood());
+        assert_eq!(retained(&agent).len(), 200);
+
+        let mut agent = IceAgent::new();
+        agent.set_max_remote_candidates(20);
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        for c in flood() {
+            agent.add_remote_candidate(c);
+        }
+        assert_eq!(retained(&agent), expected);
+        assert_eq!(agent.num_candidate_pairs(), 20);
+
+        let mut agent = IceAgent::new();
+        agent.set_max_remote_candidates(20);
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_remote_candidates(flood());
+        assert_eq!(retained(&agent), expected);
+        assert_eq!(agent.num_candidate_pairs(), 20);
+
+        let mut agent = IceAgent::new();
+        agent.set_max_remote_candidates(5);
+        agent.add_remote_candidates(flood());
+        assert_eq!(retained(&agent), expected[..5]);
+    }
+
+    #[test]
+    fn max_remote_candidates_keeps_used_and_peer_reflexive() {
+        let mut agent = IceAgent::new();
+        agent.set_max_remote_candidates(2);
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+
+        let prflx = Candidate::peer_reflexive(
+            "udp",
+            ipv4_2(),
+            ipv4_2(),
+            1,
+            Some(REMOTE_PEER_REFLEXIVE_TEMP_FOUNDATION.into()),
+            "".to_string(),
+        );
+        agent.add_remote_candidate(prflx);
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_3(), 100));
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_4(), 200));
+        assert_eq!(agent.num_candidate_pairs(), 3);
+
+        // The worst host candidate is in use.
+        let idx = agent
+            .candidate_pairs
+            .iter()
+            .position(|p| p.remote_candidate(&agent.remote_candidates).addr() == ipv4_3())
+            .unwrap();
+        agent.candidate_pairs[idx].nominate(false);
+
+        for i in 0..10_u16 {
+            let addr = SocketAddr::new([10, 0, 0, 1].into(), 5000 + i);
+            agent.add_remote_candidate(Candidate::with_priority(addr, 300 + i as u32));
+        }
+
+        let mut retained: Vec<_> = agent
+            .remote_candidates
+            .iter()
+            .filter(|c| !c.discarded())
+            .map(|c| c.prio())
+            .collect();
+        retained.sort_unstable();
+        assert_eq!(retained, [1, 100, 309]);
+    }
+
+    #[test]
+    fn max_remote_candidates_bounds_memory() {
+        let mut agent = IceAgent::new();
+        agent.set_max_remote_candidates(5);
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+
+        // Every candidate is better than the ones before, so each one replaces another.
+        for i in 0..1000_u32 {
+            let addr = SocketAddr::new([10, 0, (i / 256) as u8, (i % 256) as u8].into(), 5000);
+            agent.add_remote_candidate(Candidate::with_priority(addr, 1000 + i));
+        }
+
+        assert_eq!(agent.remote_candidates.len(), 5);
+        assert_eq!(agent.num_candidate_pairs(), 5);
+        let mut retained: Vec<_> = agent.remote_candidates.iter().map(|c| c.prio()).collect();
+        retained.sort_unstable();
+        assert_eq!(retained, [1995, 1996, 1997, 1998, 1999]);
+    }
+
+    #[test]
+    fn max_remote_candidates_when_replacing_peer_reflexive() {
+        let mut agent = IceAgent::new();
+        agent.set_max_remote_candidates(2);
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+
+        let prflx = Candidate::peer_reflexive(
+            "udp",
+            ipv4_2(),
+            ipv4_2(),
+            1,
+            Some(REMOTE_PEER_REFLEXIVE_TEMP_FOUNDATION.into()),
+            "".to_string(),
+        );
+        agent.add_remote_candidate(prflx);
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_3(), 100));
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_4(), 200));
+
+        let counted = |agent: &IceAgent| {
+            let mut prios: Vec<_> = agent
+                .remote_candidates
+                .iter()
+                .filter(|c| !c.discarded() && c.kind() != CandidateKind::PeerReflexive)
+                .map(|c| c.prio())
+                .collect();
+            prios.sort_unstable();
+            prios
+        };
+
+        // Worse than all counted candidates, the peer reflexive one is kept.
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_2(), 50));
+        assert_eq!(counted(&agent), [100, 200]);
+        assert!(agent
+            .remote_candidates
+            .iter()
+            .any(|c| c.kind() == CandidateKind::PeerReflexive && c.addr() == ipv4_2()));
+
+        // Better, so the worst counted candidate makes room.
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_2(), 300));
+        assert_eq!(counted(&agent), [200, 300]);
+        assert!(agent
+            .remote_candidates
+            .iter()
+            .all(|c| c.kind() != CandidateKind::PeerReflexive));
+    }
+
+    #[test]
+    fn add_remote_candidates_same_as_incremental() {
+        let remotes = || {
+            [
+                Candidate::host(ipv4_3(), "udp").unwrap(),
+                Candidate::host(ipv6_2(), "udp").unwrap(),
+                Candidate::host(ipv4_4(), "udp").unwrap(),
+                // Duplicate
+                Candidate::host(ipv4_3(), "udp").unwrap(),
+            ]
+        };
+
+        let new_agent = || {
+            let mut agent = IceAgent::new();
+            agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+            agent.add_local_candidate(Candidate::host(ipv6_1(), "udp").unwrap());
+            agent.add_local_candidate(Candidate::host(ipv4_2(), "udp").unwrap());
+            agent
+        };
+
+        let mut incremental = new_agent();
+        for c in remotes() {
+            incremental.add_remote_candidate(c);
+        }
+
+        let mut bulk = new_agent();
+        bulk.add_remote_candidates(remotes());
+
+        assert_eq!(bulk.remote_candidates.len(), 3);
+        assert_eq!(bulk.remote_candidates, incremental.remote_candidates);
+        assert_eq!(bulk.pair_indexes().len(), 5);
+        assert_eq!(bulk.pair_indexes(), incremental.pair_indexes());
+    }
+
+    #[test]
+    fn no_binding_request_without_remote_credentials() {
+        let mut agent = IceAgent::new();
+        let now = Instant::now();
+        agent.set_controlling(true);
+
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+        assert_eq!(agent.num_candidate_pairs(), 1);
+
+        agent.handle_timeout(now);
+        agent.stun_client_binding_request(now, 0);
+
+        assert!(agent.poll_transmit().is_none());
+        assert_eq!(agent.stats().bind_request_sent, 0);
+
+        // A response can't be checked without the remote password.
+        let reply = make_authenticated_stun_reply(TransId::new(), ipv4_1(), "pass");
+        let message = StunMessage::parse(&reply).unwrap();
+        assert!(!agent.accepts_message(&message));
+    }
+
+    #[test]
+    fn ice_lite_is_never_controlling() {
+        let mut agent = IceAgent::new();
+        agent.set_controlling(true);
+        agent.set_ice_lite(true);
+        assert!(!agent.controlling());
+
+        agent.set_controlling(true);
+        assert!(!agent.controlling());
+
+        agent.set_remote_credentials(IceCreds::new());
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+        assert_eq!(agent.num_candidate_pairs(), 1);
+
+        let mut now = Instant::now();
+        for _ in 0..50 {
+            agent.handle_timeout(now);
+            assert!(agent.poll_transmit().is_none());
+            now += Duration::from_millis(50);
+        }
+
+        assert_eq!(agent.stats().bind_request_sent, 0);
+    }
+
+    #[test]
+    fn ice_lite_recalculates_pair_prios() {
+        let mut agent = IceAgent::new();
+        agent.set_controlling(true);
+        agent.add_local_candidate(Candidate::with_priority(ipv4_1(), 100));
+        agent.add_remote_candidate(Candidate::with_priority(ipv4_3(), 200));
+        assert_eq!(
+            agent.candidate_pairs[0].prio(),
+            CandidatePair::calculate_prio(true, 200, 100)
+        );
+
+        agent.set_ice_lite(true);
+        assert_eq!(
+            agent.candidate_pairs[0].prio(),
+            CandidatePair::calculate_prio(false, 200, 100)
+        );
+    }
+
+    #[test]
+    fn ready_to_check_needs_credentials_and_pairs() {
+        let mut agent = IceAgent::new();
+        assert!(!agent.has_remote_credentials());
+        assert!(!agent.is_ready_to_check());
+
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+        assert!(!agent.is_ready_to_check());
+
+        agent.set_remote_credentials(IceCreds::new());
+        assert!(agent.has_remote_credentials());
+        assert!(agent.is_ready_to_check());
+
+        agent.invalidate_candidate(&Candidate::host(ipv4_3(), "udp").unwrap());
+        assert!(agent.has_remote_credentials());
+        assert!(!agent.is_ready_to_check());
+    }
+
+    #[test]
+    fn stats_skip_discarded_and_reset_on_ice_restart() {
+        let mut agent = IceAgent::new();
+        let remote_creds = IceCreds::new();
+        agent.set_controlling(true);
+        agent.set_remote_credentials(remote_creds.clone());
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_local_candidate(Candidate::host(ipv4_2(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+
+        let stats = agent.stats();
+        assert_eq!(stats.pairs_waiting, 2);
+        assert_eq!(stats.pairs_in_progress, 0);
+
+        let now = Instant::now();
+        agent.handle_timeout(now);
+        let transmit = agent.poll_transmit().unwrap();
+        while agent.poll_transmit().is_some() {}
+
+        let stats = agent.stats();
+        assert_eq!(stats.local_candidates, 2);
+        assert_eq!(stats.remote_candidates, 1);
+        assert_eq!(stats.candidate_pairs, 2);
+        assert_eq!(stats.pairs_waiting + stats.pairs_in_progress, 2);
+        assert!(stats.pairs_in_progress > 0);
+        assert!(stats.bind_request_sent > 0);
+
+        answer_binding_request(&mut agent, now, &transmit, &remote_creds.pass);
+        let stats = agent.stats();
+        assert_eq!(stats.pairs_succeeded, 1);
+        assert_eq!(stats.pairs_waiting + stats.pairs_in_progress, 1);
+
+        agent.invalidate_candidate(&Candidate::host(ipv4_2(), "udp").unwrap());
+        let stats = agent.stats();
+        assert_eq!(stats.local_candidates, 1);
+        assert_eq!(stats.candidate_pairs, 1);
+
+        agent.ice_restart(IceCreds::new(), true);
+        assert_eq!(
+            agent.stats(),
+            IceAgentStats {
+                local_candidates: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn binding_request_sent_from_candidate_base() {
+        let mut agent = IceAgent::new();
+        let now = Instant::now();
+        agent.set_controlling(true);
+        agent.set_remote_credentials(IceCreds::new());
+
+        // The srflx address is what the peer sees, but we must send from the base.
+        let srflx = Candidate::server_reflexive(ipv4_2(), ipv4_1(), "udp").unwrap();
+        agent.add_local_candidate(srflx);
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+
+        agent.handle_timeout(now);
+        let transmit = agent.poll_transmit().unwrap();
+
+        assert_eq!(transmit.proto, Protocol::Udp);
+        assert_eq!(transmit.source, ipv4_1());
+        assert_eq!(transmit.destination, ipv4_3());
+    }
+
+    #[test]
+    fn binding_indication_is_not_answered() {
+        let mut agent = IceAgent::new();
+        let now = Instant::now();
+        let local_creds = agent.local_credentials().clone();
+        let remote_creds = IceCreds::new();
+        agent.set_remote_credentials(remote_creds.clone());
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+
+        let username = format!("{}:{}", local_creds.ufrag, remote_creds.ufrag);
+        let indication = StunMessage::binding_indication(&username, TransId::new());
+        let buf = serialize_stun_msg(indication, &local_creds.pass);
+
+        let accepted = agent.handle_packet(
+            now,
+            StunPacket {
+                proto: Protocol::Udp,
+                source: ipv4_3(),
+                destination: ipv4_1(),
+                message: StunMessage::parse(&buf).unwrap(),
+            },
+        );
+
+        assert!(accepted);
+        assert!(agent.poll_transmit().is_none());
+        assert_eq!(agent.stats().bind_request_recv, 0);
+
+        // Without a proper username it is rejected, rather than panicking.
+        let indication = StunMessage::binding_indication("", TransId::new());
+        let buf = serialize_stun_msg(indication, &local_creds.pass);
+        let message = StunMessage::parse(&buf).unwrap();
+        assert!(!agent.accepts_message(&message));
+    }
+
+    #[test]
+    fn detects_role_conflict() {
+        let creds = IceCreds::new();
+
+        for (ours, theirs, conflict) in [
+            (true, true, true),
+            (true, false, false),
+            (false, true, false),
+            (false, false, true),
+        ] {
+            let mut agent = IceAgent::new();
+            agent.set_controlling(ours);
+
+            let buf = make_serialized_binding_request(&creds, &creds, theirs, 1);
+            let message = StunMessage::parse(&buf).unwrap();
+
+            assert_eq!(agent.is_role_conflict(&message), conflict);
+        }
+    }
+
+    #[test]
+    fn dump_checklist_shows_srflx_base() {
+        let mut agent = IceAgent::new();
+        agent.add_local_candidate(Candidate::relayed(ipv4_1(), "udp").unwrap());
+        agent.add_local_candidate(
+            Candidate::server_reflexive(ipv4_2(), "10.0.0.1:5000".parse().unwrap(), "udp").unwrap(),
+        );
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+
+        let dump = agent.dump_checklist();
+        assert!(dump.contains(&format!(" udp srflx {} base=10.0.0.1:5000 -> ", ipv4_2())));
+        assert!(dump.contains(&format!(" udp relay {} -> ", ipv4_1())));
+    }
+
+    #[test]
+    fn pair_id_stable_across_ice_restart() {
+        let mut agent = IceAgent::new();
+
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_local_candidate(Candidate::host(ipv4_2(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_4(), "udp").unwrap());
+
+        let before = agent.pair_ids();
+        assert_eq!(before.len(), 4);
+        assert_eq!(before.iter().collect::<HashSet<_>>().len(), 4);
+
+        agent.ice_restart(IceCreds::new(), true);
+        assert!(agent.pair_ids().is_empty());
+
+        // Re-added in a different order, which changes the remote indexes.
+        agent.add_remote_candidate(Candidate::host(ipv4_4(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+
+        let after = agent.pair_ids();
+        assert_eq!(after.len(), 4);
+        assert_eq!(
+            before.iter().collect::<HashSet<_>>(),
+            after.iter().collect::<HashSet<_>>()
+        );
+    }
+
     #[test]
     fn form_pairs_skip_invalidated_local() {
         let mut agent = IceAgent::new();
@@ -2012,6 +3285,35 @@ mod test {
         assert!(now2 - now1 == Duration::from_millis(50));
     }
 
+    #[test]
+    fn stun_jitter_spreads_retransmits() {
+        let now = Instant::now();
+
+        let next_attempt = || {
+            let mut agent = IceAgent::new();
+            agent.set_stun_jitter(true);
+            agent.set_remote_credentials(IceCreds::new());
+            agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+            agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+
+            agent.handle_timeout(now);
+            assert!(agent.poll_transmit().is_some());
+
+            agent.poll_timeout().unwrap()
+        };
+
+        let t1 = next_attempt();
+        let t2 = next_attempt();
+
+        // Initial RTO is 250ms, which is jittered by ±20%.
+        for t in [t1, t2] {
+            assert!(t - now >= Duration::from_millis(200));
+            assert!(t - now <= Duration::from_millis(300));
+        }
+
+        assert_ne!(t1, t2);
+    }
+
     #[test]
     fn no_disconnect_before_remote_candidates() {
         let mut agent = IceAgent::new();
@@ -2033,6 +3335,142 @@ mod test {
         }
     }
 
+    #[test]
+    fn candidate_not_in_use_after_pairs_fail() {
+        let mut agent = IceAgent::new();
+        let remote_creds = IceCreds::new();
+        agent.set_controlling(true);
+        agent.set_remote_credentials(remote_creds.clone());
+        let working = Candidate::host(ipv4_1(), "udp").unwrap();
+        let failing = Candidate::host(ipv4_2(), "udp").unwrap();
+        agent.add_local_candidate(working.clone());
+        agent.add_local_candidate(failing.clone());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+        assert_eq!(agent.num_candidate_pairs(), 2);
+
+        assert!(agent.is_candidate_in_use(&working));
+        assert!(agent.is_candidate_in_use(&failing));
+
+        let mut now = Instant::now();
+
+        while agent.num_candidate_pairs() == 2 {
+            agent.handle_timeout(now);
+
+            while let Some(transmit) = agent.poll_transmit() {
+                // Only requests from the working candidate get an answer.
+                if transmit.source != ipv4_1() {
+                    continue;
+                }
+
+                answer_binding_request(&mut agent, now, &transmit, &remote_creds.pass);
+            }
+
+            now = agent.poll_timeout().unwrap();
+        }
+
+        assert!(agent.is_candidate_in_use(&working));
+        assert!(!agent.is_candidate_in_use(&failing));
+        let unknown = Candidate::host(ipv4_4(), "udp").unwrap();
+        assert!(!agent.is_candidate_in_use(&unknown));
+    }
+
+    #[test]
+    fn srflx_candidate_in_use_through_its_base() {
+        let mut agent = IceAgent::new();
+        agent.set_remote_credentials(IceCreds::new());
+        let host = Candidate::host(ipv4_1(), "udp").unwrap();
+        let srflx = Candidate::server_reflexive(ipv4_2(), ipv4_1(), "udp").unwrap();
+        agent.add_local_candidate(host);
+        agent.add_local_candidate(srflx.clone());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+
+        // The srflx candidate is redundant with the host and gets no pair of its own.
+        assert_eq!(agent.num_candidate_pairs(), 1);
+        assert!(agent.is_candidate_in_use(&srflx));
+
+        let tcp_srflx = Candidate::server_reflexive(ipv4_2(), ipv4_1(), "tcp").unwrap();
+        assert!(!agent.is_candidate_in_use(&tcp_srflx));
+    }
+
+    #[test]
+    fn pause_stops_checks_without_burst_on_resume() {
+        let mut agent = IceAgent::new();
+        agent.set_controlling(true);
+        agent.set_remote_credentials(IceCreds::new());
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_4(), "udp").unwrap());
+        assert_eq!(agent.num_candidate_pairs(), 2);
+
+        let mut now = Instant::now();
+        agent.handle_timeout(now);
+        assert!(agent.poll_transmit().is_some());
+
+        agent.pause();
+        assert!(agent.is_paused());
+
+        // Much longer than it takes for an unanswered pair to fail.
+        for _ in 0..100 {
+            now = agent.poll_timeout().unwrap();
+            agent.handle_timeout(now);
+            assert!(agent.poll_transmit().is_none());
+        }
+        assert_eq!(agent.num_candidate_pairs(), 2);
+        let sent = agent.stats().bind_request_sent;
+
+        agent.resume();
+        assert!(!agent.is_paused());
+
+        // Both pairs are overdue, but they are sent one at a time.
+        agent.handle_timeout(now);
+        assert!(agent.poll_transmit().is_some());
+        assert!(agent.poll_transmit().is_none());
+
+        let next = agent.poll_timeout().unwrap();
+        assert_eq!(next, now + agent.timing_advance);
+        agent.handle_timeout(next);
+        assert!(agent.poll_transmit().is_some());
+        assert!(agent.poll_transmit().is_none());
+
+        assert_eq!(agent.stats().bind_request_sent, sent + 2);
+    }
+
+    #[test]
+    fn binding_attempts_stay_bounded() {
+        let mut agent = IceAgent::new();
+        let remote_creds = IceCreds::new();
+        agent.set_controlling(true);
+        agent.set_remote_credentials(remote_creds.clone());
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+
+        let max = agent.timing_config.max_retransmits();
+        let mut now = Instant::now();
+        let mut sent = 0;
+
+        while sent < 5000 {
+            agent.handle_timeout(now);
+
+            while let Some(transmit) = agent.poll_transmit() {
+                sent += 1;
+
+                // Leave every third request unanswered.
+                if sent % 3 == 0 {
+                    continue;
+                }
+
+                answer_binding_request(&mut agent, now, &transmit, &remote_creds.pass);
+            }
+
+            assert_eq!(agent.num_candidate_pairs(), 1);
+            assert!(agent.candidate_pairs[0].binding_attempt_count() <= max);
+
+            now = agent.poll_timeout().unwrap();
+        }
+
+        assert!(agent.state().is_connected());
+    }
+
     #[test]
     fn does_not_accept_response_with_unknown_transaction_id() {
         let mut agent = IceAgent::new();
@@ -2101,6 +3539,45 @@ mod test {
         assert!(stun_message.is_successful_binding_response());
     }
 
+    #[test]
+    fn peer_reflexive_remote_uses_request_priority() {
+        let mut agent = IceAgent::new();
+        let remote_creds = IceCreds::new();
+        agent.set_controlling(true);
+        agent.set_remote_credentials(remote_creds.clone());
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+
+        // Some odd prio that would not come out of any type preference.
+        let prio = 1_234_567;
+        let request =
+            make_serialized_binding_request(&agent.local_credentials, &remote_creds, false, prio);
+
+        let now = Instant::now();
+        agent.handle_packet(
+            now,
+            StunPacket {
+                proto: Protocol::Udp,
+                source: ipv4_3(),
+                destination: ipv4_1(),
+                message: StunMessage::parse(&request).unwrap(),
+            },
+        );
+        agent.handle_timeout(now);
+
+        assert_eq!(agent.remote_candidates.len(), 1);
+        let remote = &agent.remote_candidates[0];
+        assert_eq!(remote.kind(), CandidateKind::PeerReflexive);
+        assert_eq!(remote.addr(), ipv4_3());
+        assert_eq!(remote.prio(), prio);
+
+        let local_prio = agent.local_candidates[0].prio();
+        assert_eq!(agent.num_candidate_pairs(), 1);
+        assert_eq!(
+            agent.candidate_pairs[0].prio(),
+            CandidatePair::calculate_prio(true, prio, local_prio)
+        );
+    }
+
     #[test]
     pub fn discards_packet_from_unknown_candidate() {
         let mut agent = IceAgent::new();
@@ -2135,6 +3612,21 @@ mod test {
         serialize_stun_msg(binding_req, &local_creds.pass)
     }
 
+    /// Answer a binding request the agent sent, as the remote peer would.
+    fn answer_binding_request(agent: &mut IceAgent, now: Instant, request: &Transmit, pw: &str) {
+        let message = StunMessage::parse(&request.contents).unwrap();
+        let reply = make_authenticated_stun_reply(message.trans_id(), request.source, pw);
+        agent.handle_packet(
+            now,
+            StunPacket {
+                proto: request.proto,
+                source: request.destination,
+                destination: request.source,
+                message: StunMessage::parse(&reply).unwrap(),
+            },
+        );
+    }
+
     fn make_authenticated_stun_reply(tx_id: TransId, addr: SocketAddr, password: &str) -> Vec<u8> {
         let reply = StunMessage::reply(tx_id, addr);
         serialize_stun_msg(reply, password)
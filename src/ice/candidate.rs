@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize, Serializer};
 use std::collections::hash_map::DefaultHasher;
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 /// ICE candidates are network addresses used to connect to a peer.
 ///
@@ -76,6 +76,12 @@ pub struct Candidate {
     /// that are the same type.
     local_preference: Option<u32>,
 
+    /// Network cost as signaled by the remote peer (a=candidate network-cost).
+    ///
+    /// Browsers use this to tell cheap networks (ethernet, wifi) from expensive
+    /// ones (cellular). Absent is the same as cost 0.
+    network_cost: Option<u16>,
+
     /// If we discarded this candidate (for example due to being redundant
     /// against another candidate).
     discarded: bool,
@@ -124,6 +130,7 @@ impl Candidate {
             raddr,
             ufrag,
             local_preference: None,
+            network_cost: None,
             discarded: false,
         }
     }
@@ -138,8 +145,9 @@ impl Candidate {
         kind: CandidateKind,
         raddr: Option<SocketAddr>,
         ufrag: Option<String>,
+        network_cost: Option<u16>,
     ) -> Self {
-        Candidate::new(
+        let mut candidate = Candidate::new(
             Some(foundation),
             component_id,
             proto,
@@ -149,7 +157,9 @@ impl Candidate {
             kind,
             raddr,
             ufrag,
-        )
+        );
+        candidate.network_cost = network_cost;
+        candidate
     }
 
     /// Creates a host ICE candidate.
@@ -177,7 +187,9 @@ impl Candidate {
     ///
     /// Server reflexive candidates are local sockets mapped to external ip discovered
     /// via a STUN binding request.
-    /// The `base` is the local interface that this address corresponds to.
+    /// The `base` is the local interface that this address corresponds to. It is also
+    /// signalled as the related address (`raddr`/`rport`), unless the
+    /// [`IceTransportPolicy`][crate::ice::IceTransportPolicy] hides host candidates.
     pub fn server_reflexive(
         addr: SocketAddr,
         base: SocketAddr,
@@ -195,7 +207,7 @@ impl Candidate {
             addr,
             Some(base),
             CandidateKind::ServerReflexive,
-            None,
+            Some(base),
             None,
         ))
     }
@@ -204,7 +216,30 @@ impl Candidate {
     ///
     /// Relayed candidates are server sockets relaying traffic to a local socket.
     /// Allocate a TURN addr to use as a local candidate.
+    ///
+    /// This signals no related address (`raddr`/`rport`), see
+    /// [`Candidate::relayed_with_raddr`].
     pub fn relayed(addr: SocketAddr, proto: impl TryInto<Protocol>) -> Result<Self, IceError> {
+        Self::do_relayed(addr, None, proto)
+    }
+
+    /// Creates a relayed ICE candidate with a related address.
+    ///
+    /// The `raddr` is the mapped address the TURN server reported for the allocation,
+    /// which is signalled as `raddr`/`rport`.
+    pub fn relayed_with_raddr(
+        addr: SocketAddr,
+        raddr: SocketAddr,
+        proto: impl TryInto<Protocol>,
+    ) -> Result<Self, IceError> {
+        Self::do_relayed(addr, Some(raddr), proto)
+    }
+
+    fn do_relayed(
+        addr: SocketAddr,
+        raddr: Option<SocketAddr>,
+        proto: impl TryInto<Protocol>,
+    ) -> Result<Self, IceError> {
         if !is_valid_ip(addr.ip()) {
             return Err(IceError::BadCandidate(format!("invalid ip {}", addr.ip())));
         }
@@ -217,7 +252,7 @@ impl Candidate {
             addr,
             Some(addr),
             CandidateKind::Relayed,
-            None,
+            raddr,
             None,
         ))
     }
@@ -272,6 +307,22 @@ impl Candidate {
         )
     }
 
+    /// Host candidate with an explicit priority, to rig specific pair orderings in tests.
+    #[cfg(test)]
+    pub(crate) fn with_priority(addr: SocketAddr, prio: u32) -> Self {
+        Candidate::new(
+            None,
+            1, // only RTP
+            Protocol::Udp,
+            Some(prio),
+            addr,
+            Some(addr),
+            CandidateKind::Host,
+            None,
+            None,
+        )
+    }
+
     /// Candidate foundation.
     ///
     /// For local candidates this is calculated.
@@ -392,11 +443,40 @@ impl Candidate {
         self.raddr
     }
 
+    /// Replaces the related address with an unspecified one, like `raddr 0.0.0.0 rport 0`.
+    pub(crate) fn redact_raddr(&mut self) {
+        if let Some(raddr) = &mut self.raddr {
+            let ip: IpAddr = if raddr.is_ipv4() {
+                Ipv4Addr::UNSPECIFIED.into()
+            } else {
+                Ipv6Addr::UNSPECIFIED.into()
+            };
+            *raddr = SocketAddr::new(ip, 0);
+        }
+    }
+
     /// Returns the kind of this candidate.
     pub fn kind(&self) -> CandidateKind {
         self.kind
     }
 
+    pub(crate) fn network_cost(&self) -> u16 {
+        self.network_cost.unwrap_or(0)
+    }
+
+    /// Tells if this candidate describes the same transport address as another.
+    ///
+    /// Unlike `==`, this ignores fields that come and go with signaling and
+    /// state, such as ufrag, foundation, priority and whether it's discarded.
+    pub(crate) fn is_equivalent(&self, other: &Candidate) -> bool {
+        self.component_id == other.component_id
+            && self.proto == other.proto
+            && self.addr == other.addr
+            && self.base() == other.base()
+            && self.kind == other.kind
+            && self.raddr == other.raddr
+    }
+
     pub(crate) fn set_local_preference(&mut self, v: u32) {
         self.local_preference = Some(v);
     }
@@ -439,6 +519,9 @@ impl Candidate {
         if let Some(ufrag) = &self.ufrag {
             s.push_str(&format!(" ufrag {}", ufrag));
         }
+        if let Some(network_cost) = self.network_cost {
+            s.push_str(&format!(" network-cost {}", network_cost));
+        }
         s
     }
 }
@@ -627,6 +710,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn raddr_round_trip() {
+        let addr = "1.2.3.4:9876".parse().unwrap();
+        let base = "10.0.0.1:5000".parse().unwrap();
+
+        let srflx = Candidate::server_reflexive(addr, base, Protocol::Udp).unwrap();
+        assert_eq!(srflx.raddr(), Some(base));
+
+        let sdp = srflx.to_sdp_string();
+        assert!(sdp.contains(" typ srflx raddr 10.0.0.1 rport 5000"));
+
+        let parsed = Candidate::from_sdp_string(&sdp).unwrap();
+        assert_eq!(parsed.kind(), CandidateKind::ServerReflexive);
+        assert_eq!(parsed.addr(), addr);
+        assert_eq!(parsed.raddr(), Some(base));
+
+        let mapped = "5.6.7.8:4000".parse().unwrap();
+        let relay = Candidate::relayed_with_raddr(addr, mapped, Protocol::Udp).unwrap();
+        let sdp = relay.to_sdp_string();
+        assert!(sdp.contains(" typ relay raddr 5.6.7.8 rport 4000"));
+        let parsed = Candidate::from_sdp_string(&sdp).unwrap();
+        assert_eq!(parsed.kind(), CandidateKind::Relayed);
+        assert_eq!(parsed.raddr(), Some(mapped));
+
+        let host = Candidate::host(addr, Protocol::Udp).unwrap();
+        assert_eq!(host.raddr(), None);
+        assert!(!host.to_sdp_string().contains("raddr"));
+    }
+
     #[test]
     fn new_from_sdp_string() {
         let candidate = Candidate::from_sdp_string(
@@ -664,6 +776,90 @@ mod tests {
         assert!(candidates[5].contains("host"));
     }
 
+    #[test]
+    fn equivalent_ignores_volatile_fields() {
+        let a = Candidate::from_sdp_string(
+            "candidate:1 1 udp 2130706175 1.2.3.4 9876 typ srflx raddr 5.5.5.5 rport 5555 ufrag a",
+        )
+        .unwrap();
+        let mut b = Candidate::from_sdp_string(
+            "candidate:2 1 udp 1000 1.2.3.4 9876 typ srflx raddr 5.5.5.5 rport 5555 network-cost 50",
+        )
+        .unwrap();
+        b.set_discarded(true);
+
+        assert!(a != b);
+        assert!(a.is_equivalent(&b));
+
+        let other_proto = Candidate::from_sdp_string(
+            "candidate:1 1 tcp 2130706175 1.2.3.4 9876 typ srflx raddr 5.5.5.5 rport 5555",
+        )
+        .unwrap();
+        assert!(!a.is_equivalent(&other_proto));
+
+        let other_kind = Candidate::from_sdp_string(
+            "candidate:1 1 udp 2130706175 1.2.3.4 9876 typ prflx raddr 5.5.5.5 rport 5555",
+        )
+        .unwrap();
+        assert!(!a.is_equivalent(&other_kind));
+
+        let other_raddr = Candidate::from_sdp_string(
+            "candidate:1 1 udp 2130706175 1.2.3.4 9876 typ srflx raddr 6.6.6.6 rport 5555",
+        )
+        .unwrap();
+        assert!(!a.is_equivalent(&other_raddr));
+    }
+
+    #[test]
+    fn sdp_typ_token_per_kind() {
+        let addr = "1.2.3.4:9876".parse().unwrap();
+        let base = "5.5.5.5:5555".parse().unwrap();
+
+        let candidates = [
+            (Candidate::host(addr, "udp").unwrap(), "host"),
+            (Candidate::test_peer_rflx(addr, base, "udp"), "prflx"),
+            (
+                Candidate::server_reflexive(addr, base, "udp").unwrap(),
+                "srflx",
+            ),
+            (Candidate::relayed(addr, "udp").unwrap(), "relay"),
+        ];
+
+        for (candidate, typ) in candidates {
+            assert_eq!(candidate.kind().to_string(), typ);
+
+            let sdp = candidate.to_sdp_string();
+            assert!(sdp.contains(&format!(" typ {typ}")), "{sdp}");
+
+            let parsed = Candidate::from_sdp_string(&sdp).unwrap();
+            assert_eq!(parsed.kind(), candidate.kind());
+        }
+    }
+
+    #[test]
+    fn type_preference_per_kind() {
+        let addr = "1.2.3.4:9876".parse().unwrap();
+        let base = "5.5.5.5:5555".parse().unwrap();
+
+        let type_pref = |c: Candidate| c.prio() >> 24;
+
+        assert_eq!(type_pref(Candidate::host(addr, "udp").unwrap()), 126);
+        assert_eq!(type_pref(Candidate::test_peer_rflx(addr, base, "udp")), 110);
+        assert_eq!(
+            type_pref(Candidate::server_reflexive(addr, base, "udp").unwrap()),
+            100
+        );
+        assert_eq!(type_pref(Candidate::relayed(addr, "udp").unwrap()), 2);
+
+        assert_eq!(type_pref(Candidate::host(addr, "tcp").unwrap()), 90);
+        assert_eq!(type_pref(Candidate::test_peer_rflx(addr, base, "tcp")), 80);
+        assert_eq!(type_pref(Candidate::relayed(addr, "tcp").unwrap()), 1);
+
+        // A host candidate used as peer reflexive gets the prflx preference.
+        let host = Candidate::host(addr, "udp").unwrap();
+        assert_eq!(host.prio_prflx() >> 24, 110);
+    }
+
     fn host(socket: &str) -> String {
         Candidate::host(socket.parse().unwrap(), "udp")
             .unwrap()
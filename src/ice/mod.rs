@@ -4,12 +4,14 @@
 use thiserror::Error;
 
 mod agent;
-pub use agent::{IceAgent, IceAgentEvent, IceConnectionState, IceCreds};
+pub use agent::{IceAgent, IceAgentEvent, IceAgentStats, IceConnectionState, IceCreds};
+pub use agent::{IceTimings, IceTransportPolicy};
 
 mod candidate;
 pub use candidate::{Candidate, CandidateKind};
 
 mod pair;
+pub use pair::PairId;
 
 /// Errors from the ICE agent.
 #[allow(missing_docs)]
@@ -21,7 +23,6 @@ pub enum IceError {
 
 #[cfg(test)]
 mod test {
-    use super::agent::IceAgentStats;
     use super::*;
 
     #[test]
@@ -49,10 +50,17 @@ mod test {
             a1.stats(),
             IceAgentStats {
                 bind_request_sent: 9,
+                bind_request_retransmit: 8,
                 bind_success_recv: 0,
                 bind_request_recv: 0,
                 discovered_recv_count: 0,
                 nomination_send_count: 0,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 0,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 0,
             }
         );
 
@@ -60,10 +68,17 @@ mod test {
             a2.stats(),
             IceAgentStats {
                 bind_request_sent: 9,
+                bind_request_retransmit: 8,
                 bind_success_recv: 0,
                 bind_request_recv: 0,
                 discovered_recv_count: 0,
                 nomination_send_count: 0,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 0,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 0,
             }
         );
     }
@@ -198,10 +213,17 @@ mod test {
             a1.stats(),
             IceAgentStats {
                 bind_request_sent: 11,
+                bind_request_retransmit: 8,
                 bind_success_recv: 2,
                 bind_request_recv: 11,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 0,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 0,
             }
         );
 
@@ -209,10 +231,17 @@ mod test {
             a2.stats(),
             IceAgentStats {
                 bind_request_sent: 11,
+                bind_request_retransmit: 8,
                 bind_success_recv: 2,
                 bind_request_recv: 2,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 0,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 0,
             }
         );
     }
@@ -242,10 +271,17 @@ mod test {
             a1.stats(),
             IceAgentStats {
                 bind_request_sent: 2,
+                bind_request_retransmit: 0,
                 bind_success_recv: 2,
                 bind_request_recv: 2,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 1,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 1,
             }
         );
 
@@ -253,14 +289,158 @@ mod test {
             a2.stats(),
             IceAgentStats {
                 bind_request_sent: 3,
+                bind_request_retransmit: 0,
                 bind_success_recv: 2,
                 bind_request_recv: 2,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 1,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 1,
             }
         );
     }
 
+    #[test]
+    pub fn host_host_timings() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        assert_eq!(a1.timings(), IceTimings::default());
+
+        loop {
+            if a1.state() == IceConnectionState::Completed
+                && a2.state() == IceConnectionState::Completed
+            {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        for a in [&a1, &a2] {
+            let t = a.timings();
+            let first_check = t.first_check.unwrap();
+            let connected = t.connected.unwrap();
+            let completed = t.completed.unwrap();
+
+            assert!(first_check <= connected);
+            assert!(connected <= completed);
+            assert!(t.time_to_connected().unwrap() <= t.time_to_completed().unwrap());
+        }
+
+        a1.ice_restart(IceCreds::new(), true);
+        assert_eq!(a1.timings(), IceTimings::default());
+    }
+
+    #[test]
+    pub fn host_host_consistent_nomination() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        for c in [host("1.1.1.1:1000", "udp"), host("1.1.1.2:1000", "udp")] {
+            a1.add_local_candidate(c.clone());
+            a2.add_remote_candidate(c);
+        }
+        for c in [host("2.2.2.1:1000", "udp"), host("2.2.2.2:1000", "udp")] {
+            a2.add_local_candidate(c.clone());
+            a1.add_remote_candidate(c);
+        }
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        let nominated = |e: &IceAgentEvent| matches!(e, IceAgentEvent::NominatedSend { .. });
+
+        loop {
+            if a1.state().is_connected()
+                && a2.state().is_connected()
+                && a1.has_event(nominated)
+                && a2.has_event(nominated)
+            {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        fn last_nominated(a: &TestAgent) -> (SocketAddr, SocketAddr) {
+            a.events
+                .iter()
+                .rev()
+                .find_map(|(_, e)| match e {
+                    IceAgentEvent::NominatedSend {
+                        source,
+                        destination,
+                        ..
+                    } => Some((*source, *destination)),
+                    _ => None,
+                })
+                .expect("a nominated pair")
+        }
+
+        // Both sides must agree on the same pair, seen from opposite ends.
+        let (source1, destination1) = last_nominated(&a1);
+        let (source2, destination2) = last_nominated(&a2);
+        assert_eq!(source1, destination2);
+        assert_eq!(destination1, source2);
+    }
+
+    #[test]
+    pub fn host_host_dump_checklist() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+        // Nothing listens here, this pair never succeeds.
+        a1.add_remote_candidate(host("2.2.2.2:9999", "udp"));
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        let dump = a1.dump_checklist();
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let ok = lines
+            .iter()
+            .find(|l| l.contains("-> host 2.2.2.2:1000"))
+            .unwrap();
+        assert!(ok.starts_with("* udp host 1.1.1.1:1000"));
+        let id = a1.nominated_send_id().unwrap();
+        assert!(ok.ends_with(&format!(" id={id}")));
+        assert!(ok.contains("state=Succeeded"));
+        assert!(ok.contains("rtt=Some("));
+
+        let dropped = lines
+            .iter()
+            .find(|l| l.contains("-> host 2.2.2.2:9999"))
+            .unwrap();
+        assert!(dropped.starts_with("  udp host 1.1.1.1:1000"));
+        assert!(!dropped.contains("state=Succeeded"));
+        assert!(dropped.contains("rtt=None"));
+    }
+
     #[test]
     pub fn no_respond_to_stun_request_on_invalidated_candidate() {
         let mut a1 = TestAgent::new(info_span!("L"));
@@ -483,10 +663,17 @@ mod test {
             a1.stats(),
             IceAgentStats {
                 bind_request_sent: 0,
+                bind_request_retransmit: 0,
                 bind_success_recv: 0,
                 bind_request_recv: 0,
                 discovered_recv_count: 0,
                 nomination_send_count: 0,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 0,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 0,
             }
         );
 
@@ -494,10 +681,17 @@ mod test {
             a2.stats(),
             IceAgentStats {
                 bind_request_sent: 9,
+                bind_request_retransmit: 8,
                 bind_success_recv: 0,
                 bind_request_recv: 0,
                 discovered_recv_count: 0,
                 nomination_send_count: 0,
+                local_candidates: 1,
+                remote_candidates: 1,
+                candidate_pairs: 0,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 0,
             }
         );
     }
@@ -528,10 +722,17 @@ mod test {
             a1.stats(),
             IceAgentStats {
                 bind_request_sent: 2,
+                bind_request_retransmit: 0,
                 bind_success_recv: 2,
                 bind_request_recv: 1,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
+                local_candidates: 2,
+                remote_candidates: 1,
+                candidate_pairs: 1,
+                pairs_waiting: 0,
+                pairs_in_progress: 0,
+                pairs_succeeded: 1,
             }
         );
 
@@ -539,10 +740,17 @@ mod test {
             a2.stats(),
             IceAgentStats {
                 bind_request_sent: 3,
+                bind_request_retransmit: 1,
                 bind_success_recv: 1,
                 bind_request_recv: 2,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
+                local_candidates: 1,
+                remote_candidates: 2,
+                candidate_pairs: 2,
+                pairs_waiting: 0,
+                pairs_in_progress: 1,
+                pairs_succeeded: 1,
             }
         );
     }
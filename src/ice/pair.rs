@@ -1,8 +1,10 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::VecDeque;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::time::{Duration, Instant};
 
-use crate::io::{Id, StunTiming, TransId, DEFAULT_MAX_RETRANSMITS};
+use crate::io::{StunTiming, TransId, DEFAULT_MAX_RETRANSMITS};
 use crate::Candidate;
 
 // When running ice-lite we need a cutoff when we consider the remote definitely gone.
@@ -25,6 +27,11 @@ pub struct CandidatePair {
     /// Calculated prio given the candidates.
     prio: u64,
 
+    /// Network cost of the pair, the higher of the two candidates.
+    ///
+    /// Used to prefer cheaper networks between pairs of equal priority.
+    network_cost: u16,
+
     /// Current state of this pair. Start in Waiting (there is
     /// no frozen state since there is only one data stream).
     state: CheckState,
@@ -103,23 +110,49 @@ pub struct BindingAttempt {
     nominated: bool,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct PairId([u8; 20]);
+/// Identifies the network path of a candidate pair.
+///
+/// The id is stable when the same candidates are added again, such as after an
+/// ICE restart, which makes it possible to follow a path in telemetry.
+///
+/// It is a hash of the local base and remote address, and is only stable within
+/// one build. It can change with another version of Rust or str0m.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PairId(u64);
+
+impl fmt::Display for PairId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
 
-impl Default for PairId {
-    fn default() -> Self {
-        PairId(Id::random().into_array())
+impl PairId {
+    pub(crate) fn new(local: &Candidate, remote: &Candidate) -> Self {
+        // There is only ever one pair per local base / remote addr (see the
+        // pruning of redundant pairs in the agent), so this is what defines the path.
+        let mut hasher = DefaultHasher::new();
+        local.proto().hash(&mut hasher);
+        local.base().hash(&mut hasher);
+        remote.addr().hash(&mut hasher);
+        PairId(hasher.finish())
     }
 }
 
 impl CandidatePair {
-    pub fn new(local_idx: usize, remote_idx: usize, prio: u64) -> Self {
+    pub fn new(
+        id: PairId,
+        local_idx: usize,
+        remote_idx: usize,
+        prio: u64,
+        network_cost: u16,
+    ) -> Self {
         CandidatePair {
             local_idx,
             remote_idx,
             prio,
+            network_cost,
             binding_attempts: VecDeque::with_capacity(DEFAULT_MAX_RETRANSMITS * 2),
-            id: Default::default(),
+            id,
             valid_idx: Default::default(),
             state: Default::default(),
             cached_next_attempt_time: Default::default(),
@@ -170,6 +203,10 @@ impl CandidatePair {
         self.prio
     }
 
+    pub fn set_prio(&mut self, prio: u64) {
+        self.prio = prio;
+    }
+
     pub fn state(&self) -> CheckState {
         self.state
     }
@@ -191,6 +228,10 @@ impl CandidatePair {
         now - t < RECENT_BINDING_REQUEST
     }
 
+    pub fn nomination_state(&self) -> NominationState {
+        self.nomination_state
+    }
+
     pub fn is_nominated(&self) -> bool {
         !matches!(self.nomination_state, NominationState::None)
     }
@@ -256,6 +297,23 @@ impl CandidatePair {
         last.trans_id
     }
 
+    pub fn binding_attempt_count(&self) -> usize {
+        self.binding_attempts.len()
+    }
+
+    /// Round trip time of the latest answered binding attempt.
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.binding_attempts
+            .iter()
+            .rev()
+            .find_map(|b| b.respone_recv.map(|r| r - b.request_sent))
+    }
+
+    /// Tells if the latest binding attempt is still waiting for a response.
+    pub fn has_unanswered_attempt(&self) -> bool {
+        self.unanswered().is_some()
+    }
+
     /// Tells if this pair caused the binding request for a STUN transaction id.
     pub fn has_binding_attempt(&self, trans_id: TransId) -> bool {
         self.binding_attempts.iter().any(|b| b.trans_id == trans_id)
@@ -338,14 +396,14 @@ impl CandidatePair {
 
             let send_count = unanswered_count.unwrap_or(self.binding_attempts.len());
 
-            last + timing_config.stun_resend_delay(send_count)
+            last + timing_config.jittered(timing_config.stun_resend_delay(send_count))
         } else {
             // No previous attempt, do next retry straight away.
             now
         };
 
         // At least do a check at this time.
-        let min = now + timing_config.max_rto();
+        let min = now + timing_config.jittered(timing_config.max_rto());
 
         let at_least = next.min(min);
 
@@ -399,8 +457,11 @@ impl PartialOrd for CandidatePair {
 
 impl Ord for CandidatePair {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // reverse since we want highest prio first.
-        self.prio.cmp(&other.prio).reverse()
+        // reverse since we want highest prio first, then lowest network cost.
+        self.prio
+            .cmp(&other.prio)
+            .reverse()
+            .then_with(|| self.network_cost.cmp(&other.network_cost))
     }
 }
 
@@ -15,6 +15,7 @@ pub struct StunTiming {
     pub(crate) initial_rto: Duration,
     pub(crate) max_retransmits: usize,
     pub(crate) max_rto: Duration,
+    pub(crate) jitter: bool,
 }
 
 impl StunTiming {
@@ -54,6 +55,18 @@ impl StunTiming {
     pub fn max_rto(&self) -> Duration {
         self.max_rto
     }
+
+    /// Randomize a delay by ±20%, if jitter is enabled.
+    ///
+    /// This spreads out the timers of many agents started at the same time, as recommended
+    /// for the consent interval in https://datatracker.ietf.org/doc/html/rfc7675#section-5.1
+    pub fn jittered(&self, delay: Duration) -> Duration {
+        if !self.jitter {
+            return delay;
+        }
+
+        delay.mul_f32(0.8 + 0.4 * NonCryptographicRng::f32())
+    }
 }
 
 // Consult libwebrtc for default values here.
@@ -63,6 +76,7 @@ impl Default for StunTiming {
             initial_rto: Duration::from_millis(250),
             max_retransmits: DEFAULT_MAX_RETRANSMITS,
             max_rto: Duration::from_millis(3000), // libwebrtc uses 8000 here but we want faster detection of gone peers.
+            jitter: false,
         }
     }
 }
@@ -77,6 +91,10 @@ pub enum StunError {
     /// An IO error occurred while handling a STUN message.
     #[error("STUN io: {0}")]
     Io(#[from] io::Error),
+
+    /// An ERROR-CODE attribute with a code or reason phrase that is out of range.
+    #[error("STUN invalid error code: {0}")]
+    InvalidErrorCode(String),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -108,6 +126,7 @@ pub struct StunMessage<'a> {
     class: Class,
     trans_id: TransId,
     attrs: Attributes<'a>,
+    raw_attrs: &'a [u8],
     integrity: &'a [u8],
     integrity_len: u16,
 }
@@ -115,7 +134,9 @@ pub struct StunMessage<'a> {
 impl<'a> StunMessage<'a> {
     /// Parse a STUN message from a slice of bytes.
     pub fn parse(buf: &[u8]) -> Result<StunMessage, StunError> {
-        if buf.len() < 4 {
+        // This is parsing untrusted input from the network. Every length
+        // must be checked before it is used to index.
+        if buf.len() < 20 {
             return Err(StunError::Parse("Buffer too short".into()));
         }
 
@@ -149,6 +170,23 @@ impl<'a> StunMessage<'a> {
             return Err(StunError::Parse("No message integrity in incoming".into()));
         }
 
+        // FINGERPRINT is optional and an unkeyed CRC, so it only catches corrupt or
+        // non-STUN packets. It is no protection against forgery, that is what
+        // MESSAGE-INTEGRITY is for. A present but wrong fingerprint is malformed.
+        if let Some(fingerprint) = attrs.fingerprint {
+            // FINGERPRINT must be the last attribute, and covers everything before it.
+            let fp_off = buf.len() - 8;
+            if buf[fp_off..(fp_off + 2)] != Attributes::FINGERPRINT.to_be_bytes() {
+                return Err(StunError::Parse(
+                    "Fingerprint is not the last attribute".into(),
+                ));
+            }
+            let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&buf[..fp_off]) ^ 0x5354_554e;
+            if crc != fingerprint {
+                return Err(StunError::Parse("Fingerprint mismatch".into()));
+            }
+        }
+
         // length including message integrity attribute
         let integrity_len = (message_integrity_offset + 4 + 20) as u16;
 
@@ -157,12 +195,14 @@ impl<'a> StunMessage<'a> {
         let integrity = &buf[0..(message_integrity_offset + 20)];
 
         if method == Method::Binding && class == Class::Success {
-            if attrs.xor_mapped_address.is_none() {
+            if attrs.xor_mapped_address.is_none() && attrs.mapped_address.is_none() {
                 return Err(StunError::Parse("STUN packet missing mapped addr".into()));
             }
         } else if method == Method::Binding && class == Class::Request {
             if attrs.split_username().is_none() {
-                return Err(StunError::Parse("STUN packet missing username".into()));
+                return Err(StunError::Parse(
+                    "STUN packet missing or malformed username".into(),
+                ));
             }
             if attrs.priority.is_none() {
                 return Err(StunError::Parse("STUN packet missing mapped addr".into()));
@@ -174,6 +214,7 @@ impl<'a> StunMessage<'a> {
             method,
             trans_id,
             attrs,
+            raw_attrs: &buf[20..],
             integrity,
             integrity_len,
         })
@@ -226,6 +267,26 @@ impl<'a> StunMessage<'a> {
                 use_candidate,
                 ..Default::default()
             },
+            raw_attrs: &[],
+            integrity: &[],
+            integrity_len: 0,
+        }
+    }
+
+    /// Constructs a new BINDING indication.
+    ///
+    /// Indications are not answered, which makes them a lightweight keepalive.
+    #[allow(dead_code)]
+    pub(crate) fn binding_indication(username: &'a str, trans_id: TransId) -> Self {
+        StunMessage {
+            class: Class::Indication,
+            method: Method::Binding,
+            trans_id,
+            attrs: Attributes {
+                username: Some(username),
+                ..Default::default()
+            },
+            raw_attrs: &[],
             integrity: &[],
             integrity_len: 0,
         }
@@ -241,26 +302,107 @@ impl<'a> StunMessage<'a> {
                 xor_mapped_address: Some(mapped_address),
                 ..Default::default()
             },
+            raw_attrs: &[],
             integrity: &[],
             integrity_len: 0,
         }
     }
 
+    /// Constructs a new STUN BINDING error response.
+    ///
+    /// The `code` must be in the range 300-699, such as 487 for a role conflict,
+    /// and the `reason` at most 128 bytes.
+    #[allow(dead_code)]
+    pub(crate) fn binding_error(
+        code: u16,
+        reason: &'a str,
+        trans_id: TransId,
+    ) -> Result<StunMessage<'a>, StunError> {
+        if !(300..700).contains(&code) {
+            return Err(StunError::InvalidErrorCode(format!(
+                "code not in range: {code}"
+            )));
+        }
+        // Same limit as when parsing, see decode_str.
+        if reason.len() > 128 {
+            return Err(StunError::InvalidErrorCode(format!(
+                "too long reason len: {}",
+                reason.len()
+            )));
+        }
+        Ok(StunMessage {
+            class: Class::Failure,
+            method: Method::Binding,
+            trans_id,
+            attrs: Attributes {
+                error_code: Some((code, reason)),
+                ..Default::default()
+            },
+            raw_attrs: &[],
+            integrity: &[],
+            integrity_len: 0,
+        })
+    }
+
     /// If present, splits the value of the USERNAME attribute into local and remote (separated by `:`).
     pub fn split_username(&self) -> Option<(&str, &str)> {
         self.attrs.split_username()
     }
 
-    /// If present, returns the value of XOR-MAPPED-ADDRESS attribute.
+    /// All attributes of a parsed message as type and raw value, in wire order.
+    ///
+    /// This includes attributes that are otherwise ignored, which is useful for
+    /// debugging. Messages that were not parsed have no raw attributes.
+    pub fn attributes(&self) -> impl Iterator<Item = (u16, &'a [u8])> {
+        let mut buf = self.raw_attrs;
+
+        std::iter::from_fn(move || {
+            if buf.len() < 4 {
+                return None;
+            }
+            let typ = u16::from_be_bytes([buf[0], buf[1]]);
+            let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+            let pad_len = (len + 3) & !3;
+            let value = buf.get(4..(4 + len))?;
+            buf = buf.get((4 + pad_len)..).unwrap_or_default();
+            Some((typ, value))
+        })
+    }
+
+    /// The mapped address, preferring XOR-MAPPED-ADDRESS over MAPPED-ADDRESS.
+    ///
+    /// Classic (RFC 3489) STUN servers only send MAPPED-ADDRESS.
     pub(crate) fn mapped_address(&self) -> Option<SocketAddr> {
+        self.attrs.xor_mapped_address.or(self.attrs.mapped_address)
+    }
+
+    /// If present, returns the value of XOR-MAPPED-ADDRESS attribute.
+    #[cfg(test)]
+    pub(crate) fn xor_mapped_address(&self) -> Option<SocketAddr> {
         self.attrs.xor_mapped_address
     }
 
+    /// If present, returns the code and reason phrase of the ERROR-CODE attribute.
+    #[allow(dead_code)]
+    pub(crate) fn error_code(&self) -> Option<(u16, &'a str)> {
+        self.attrs.error_code
+    }
+
     /// If present, returns the value of the PRIORITY attribute.
     pub(crate) fn prio(&self) -> Option<u32> {
         self.attrs.priority
     }
 
+    /// If present, returns the tie breaker of the ICE-CONTROLLING attribute.
+    pub(crate) fn ice_controlling(&self) -> Option<u64> {
+        self.attrs.ice_controlling
+    }
+
+    /// If present, returns the tie breaker of the ICE-CONTROLLED attribute.
+    pub(crate) fn ice_controlled(&self) -> Option<u64> {
+        self.attrs.ice_controlled
+    }
+
     /// Whether this message has the USE-CANDIDATE attribute.
     pub(crate) fn use_candidate(&self) -> bool {
         self.attrs.use_candidate
@@ -419,6 +561,7 @@ pub struct Attributes<'a> {
     error_code: Option<(u16, &'a str)>,     // 300-699 and reason phrase < 128 utf8 chars
     realm: Option<&'a str>,                 // < 128 utf8 chars
     nonce: Option<&'a str>,                 // < 128 utf8 chars
+    mapped_address: Option<SocketAddr>,     // 0x0001 https://tools.ietf.org/html/rfc3489
     xor_mapped_address: Option<SocketAddr>, // 0x0020
     software: Option<&'a str>,              // 0x0022
     fingerprint: Option<u32>,               // crc32
@@ -448,6 +591,9 @@ impl<'a> fmt::Debug for Attributes<'a> {
         if let Some(value) = self.nonce {
             debug_struct.field("nonce", &value);
         }
+        if let Some(value) = self.mapped_address {
+            debug_struct.field("mapped_address", &value);
+        }
         if let Some(value) = self.xor_mapped_address {
             debug_struct.field("xor_mapped_address", &value);
         }
@@ -484,9 +630,13 @@ impl<'a> Attributes<'a> {
         // 062g is the remote.
 
         let v = self.username?;
+
+        // Split on the first colon. ufrags can't contain colons, so anything after
+        // a second colon ends up in remote, which then won't match any ufrag.
         let idx = v.find(':')?;
 
-        if idx + 1 >= v.len() {
+        // Both halves must be non-empty.
+        if idx == 0 || idx + 1 >= v.len() {
             return None;
         }
 
@@ -525,7 +675,7 @@ impl<'a> Attributes<'a> {
         let username = self
             .username
             .map(|v| {
-                let pad = 4 - (v.as_bytes().len() % 4) % 4;
+                let pad = (4 - v.as_bytes().len() % 4) % 4;
                 ATTR_TLV_LENGTH + v.len() + pad
             })
             .unwrap_or_default();
@@ -541,6 +691,10 @@ impl<'a> Attributes<'a> {
             .priority
             .map(|p| ATTR_TLV_LENGTH + p.to_le_bytes().len())
             .unwrap_or_default();
+        let error_code = self
+            .error_code
+            .map(|(_, r)| ATTR_TLV_LENGTH + 4 + r.len() + (4 - r.len() % 4) % 4)
+            .unwrap_or_default();
         let address = self
             .xor_mapped_address
             .map(|a| ATTR_TLV_LENGTH + if a.is_ipv4() { 8 } else { 20 })
@@ -551,7 +705,13 @@ impl<'a> Attributes<'a> {
             0
         };
 
-        username + ice_controlled + ice_controlling + priority + address + use_candidate
+        username
+            + ice_controlled
+            + ice_controlling
+            + priority
+            + error_code
+            + address
+            + use_candidate
     }
 
     fn to_bytes(self, vec: &mut dyn Write, trans_id: &[u8]) -> io::Result<()> {
@@ -559,7 +719,7 @@ impl<'a> Attributes<'a> {
             vec.write_all(&Self::USERNAME.to_be_bytes())?;
             vec.write_all(&(v.as_bytes().len() as u16).to_be_bytes())?;
             vec.write_all(v.as_bytes())?;
-            let pad = 4 - (v.as_bytes().len() % 4) % 4;
+            let pad = (4 - v.as_bytes().len() % 4) % 4;
             for _ in 0..pad {
                 vec.write_all(&[0])?;
             }
@@ -579,6 +739,16 @@ impl<'a> Attributes<'a> {
             vec.write_all(&4_u16.to_be_bytes())?;
             vec.write_all(&v.to_be_bytes())?;
         }
+        if let Some((code, reason)) = self.error_code {
+            vec.write_all(&Self::ERROR_CODE.to_be_bytes())?;
+            vec.write_all(&((4 + reason.len()) as u16).to_be_bytes())?;
+            vec.write_all(&[0, 0, (code / 100) as u8, (code % 100) as u8])?;
+            vec.write_all(reason.as_bytes())?;
+            let pad = (4 - reason.len() % 4) % 4;
+            for _ in 0..pad {
+                vec.write_all(&[0])?;
+            }
+        }
         if let Some(v) = self.xor_mapped_address {
             let mut buf = [0_u8; 20];
             let len = encode_xor(v, &mut buf, trans_id);
@@ -610,6 +780,11 @@ impl<'a> Attributes<'a> {
             if buf.is_empty() {
                 break;
             }
+            if buf.len() < 4 {
+                return Err(StunError::Parse(format!(
+                    "Truncated STUN attribute header at offset {off}"
+                )));
+            }
             let typ = u16::from_le_bytes([buf[1], buf[0]]);
             let len = u16::from_le_bytes([buf[3], buf[2]]) as usize;
             // trace!(
@@ -628,7 +803,7 @@ impl<'a> Attributes<'a> {
             if !ignore_rest || typ == Self::FINGERPRINT {
                 match typ {
                     Self::MAPPED_ADDRESS => {
-                        warn!("STUN got MappedAddress");
+                        attributes.mapped_address = Some(decode_mapped(&buf[4..(4 + len)])?);
                     }
                     Self::USERNAME => {
                         attributes.username = Some(decode_str(typ, &buf[4..], len)?);
@@ -646,6 +821,9 @@ impl<'a> Attributes<'a> {
                         attributes.message_integrity = Some(&buf[4..24]);
                     }
                     Self::ERROR_CODE => {
+                        if len < 4 {
+                            return Err(StunError::Parse("Error code shorter than 4".into()));
+                        }
                         if buf[4] != 0 || buf[5] != 0 || buf[6] & 0b1111_1000 != 0 {
                             return Err(StunError::Parse("Expected 0 at top of error code".into()));
                         }
@@ -668,7 +846,8 @@ impl<'a> Attributes<'a> {
                         attributes.nonce = Some(decode_str(typ, &buf[4..], len)?);
                     }
                     Self::XOR_MAPPED_ADDRESS => {
-                        attributes.xor_mapped_address = Some(decode_xor(&buf[4..], trans_id)?);
+                        attributes.xor_mapped_address =
+                            Some(decode_xor(&buf[4..(4 + len)], trans_id)?);
                     }
                     Self::SOFTWARE => {
                         attributes.software = Some(decode_str(typ, &buf[4..], len)?);
@@ -692,6 +871,11 @@ impl<'a> Attributes<'a> {
                         warn!("STUN got AlternateServer");
                     }
                     Self::FINGERPRINT => {
+                        if len != 4 {
+                            return Err(StunError::Parse(
+                                "Fingerprint that isnt 4 in length".into(),
+                            ));
+                        }
                         let bytes = [buf[4], buf[5], buf[6], buf[7]];
                         attributes.fingerprint = Some(u32::from_be_bytes(bytes));
                     }
@@ -775,7 +959,57 @@ fn encode_xor(addr: SocketAddr, buf: &mut [u8; 20], trans_id: &[u8]) -> usize {
     }
 }
 
+fn decode_mapped(buf: &[u8]) -> Result<SocketAddr, StunError> {
+    let expected_len = match buf.get(1) {
+        Some(1) => 8,
+        Some(2) => 20,
+        _ => 0,
+    };
+    if expected_len > 0 && buf.len() != expected_len {
+        return Err(StunError::Parse(format!(
+            "MappedAddress of wrong length: {}",
+            buf.len()
+        )));
+    }
+    if buf.len() < 4 {
+        return Err(StunError::Parse("MappedAddress too short".into()));
+    }
+
+    let port = ((buf[2] as u16) << 8) | (buf[3] as u16);
+    let ip_buf = &buf[4..];
+    let ip = match buf[1] {
+        1 => {
+            let bytes: [u8; 4] = ip_buf.try_into().expect("checked length");
+            IpAddr::V4(bytes.into())
+        }
+        2 => {
+            let bytes: [u8; 16] = ip_buf.try_into().expect("checked length");
+            IpAddr::V6(bytes.into())
+        }
+        e => {
+            return Err(StunError::Parse(format!("Invalid address family: {e:?}")));
+        }
+    };
+
+    Ok(SocketAddr::new(ip, port))
+}
+
 fn decode_xor(buf: &[u8], trans_id: TransId) -> Result<SocketAddr, StunError> {
+    let expected_len = match buf.get(1) {
+        Some(1) => 8,
+        Some(2) => 20,
+        _ => 0,
+    };
+    if expected_len > 0 && buf.len() != expected_len {
+        return Err(StunError::Parse(format!(
+            "XorMappedAddress of wrong length: {}",
+            buf.len()
+        )));
+    }
+    if buf.len() < 4 {
+        return Err(StunError::Parse("XorMappedAddress too short".into()));
+    }
+
     let port = (((buf[2] as u16) << 8) | (buf[3] as u16)) ^ 0x2112;
     let ip_buf = &buf[4..];
     let ip = match buf[1] {
@@ -818,6 +1052,7 @@ impl<'a> fmt::Debug for StunMessage<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::io::DATAGRAM_MTU;
     use std::net::SocketAddrV4;
     use systemstat::Ipv4Addr;
 
@@ -860,6 +1095,7 @@ mod test {
             error_code: Some((401, "Unauthorized")),
             realm: Some("baz"),
             nonce: Some("abcd"),
+            mapped_address: Some(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1))),
             xor_mapped_address: Some(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))),
             software: Some("str0m"),
             fingerprint: Some(9999),
@@ -874,8 +1110,171 @@ mod test {
 
         assert_eq!(
             dbg_print,
-            r#"Attributes { username: "foo", message_integrity: [48, 48, 48, 48], error_code: (401, "Unauthorized"), realm: "baz", nonce: "abcd", xor_mapped_address: 127.0.0.1:0, software: "str0m", fingerprint: 9999, priority: 1, use_candidate: true, ice_controlled: 10, ice_controlling: 100, network_cost: (10, 10) }"#
+            r#"Attributes { username: "foo", message_integrity: [48, 48, 48, 48], error_code: (401, "Unauthorized"), realm: "baz", nonce: "abcd", mapped_address: 127.0.0.1:1, xor_mapped_address: 127.0.0.1:0, software: "str0m", fingerprint: 9999, priority: 1, use_candidate: true, ice_controlled: 10, ice_controlling: 100, network_cost: (10, 10) }"#
+        );
+    }
+
+    #[test]
+    fn raw_attributes() {
+        let mut buf = vec![0_u8; DATAGRAM_MTU];
+        // A username that is a multiple of 4 must not get any padding.
+        let msg = StunMessage::binding_request("abc:efgh", TransId::new(), true, 7, 42, true);
+        let n = msg.to_bytes("pass", &mut buf).unwrap();
+        let parsed = StunMessage::parse(&buf[..n]).unwrap();
+
+        let attrs: Vec<_> = parsed.attributes().collect();
+        let types: Vec<_> = attrs.iter().map(|(t, _)| *t).collect();
+
+        assert_eq!(
+            types,
+            [
+                Attributes::USERNAME,
+                Attributes::ICE_CONTROLLING,
+                Attributes::PRIORITY,
+                Attributes::USE_CANDIDATE,
+                Attributes::MESSAGE_INTEGRITY,
+                Attributes::FINGERPRINT,
+            ]
         );
+        assert_eq!(attrs[0].1, b"abc:efgh");
+        assert_eq!(attrs[1].1, 7_u64.to_be_bytes());
+        assert_eq!(attrs[2].1, 42_u32.to_be_bytes());
+        assert!(attrs[3].1.is_empty());
+        assert_eq!(attrs[4].1.len(), 20);
+
+        // Unparsed messages have no raw attributes.
+        assert_eq!(msg.attributes().count(), 0);
+    }
+
+    #[test]
+    fn mapped_address_plain_and_xor() {
+        // A binding success with a single address attribute and a dummy MESSAGE-INTEGRITY.
+        fn success(trans_id: TransId, typ: u16, value: &[u8]) -> Vec<u8> {
+            let mut buf = vec![0x01, 0x01, 0, 0];
+            buf.extend_from_slice(MAGIC);
+            buf.extend_from_slice(&trans_id.0);
+            buf.extend_from_slice(&typ.to_be_bytes());
+            buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            buf.extend_from_slice(value);
+            buf.extend_from_slice(&Attributes::MESSAGE_INTEGRITY.to_be_bytes());
+            buf.extend_from_slice(&20_u16.to_be_bytes());
+            buf.extend_from_slice(&[0; 20]);
+            let len = (buf.len() - 20) as u16;
+            buf[2..4].copy_from_slice(&len.to_be_bytes());
+            buf
+        }
+
+        let addrs: [SocketAddr; 2] = [
+            "1.2.3.4:5678".parse().unwrap(),
+            "[::1:2]:5678".parse().unwrap(),
+        ];
+
+        for addr in addrs {
+            let trans_id = TransId::new();
+
+            let mut xor = [0_u8; 20];
+            let n = encode_xor(addr, &mut xor, &trans_id.0);
+            let buf = success(trans_id, Attributes::XOR_MAPPED_ADDRESS, &xor[..n]);
+            let msg = StunMessage::parse(&buf).unwrap();
+            assert_eq!(msg.xor_mapped_address(), Some(addr));
+            assert_eq!(msg.mapped_address(), Some(addr));
+
+            let mut plain = vec![0, if addr.is_ipv4() { 1 } else { 2 }];
+            plain.extend_from_slice(&addr.port().to_be_bytes());
+            match addr.ip() {
+                IpAddr::V4(v) => plain.extend_from_slice(&v.octets()),
+                IpAddr::V6(v) => plain.extend_from_slice(&v.octets()),
+            }
+            let buf = success(trans_id, Attributes::MAPPED_ADDRESS, &plain);
+            let msg = StunMessage::parse(&buf).unwrap();
+            assert_eq!(msg.xor_mapped_address(), None);
+            assert_eq!(msg.mapped_address(), Some(addr));
+        }
+    }
+
+    #[test]
+    fn binding_error_round_trip() {
+        let mut buf = vec![0_u8; DATAGRAM_MTU];
+        let trans_id = TransId::new();
+        // The reason phrase needs padding.
+        let msg = StunMessage::binding_error(487, "Role Conflict", trans_id).unwrap();
+        let n = msg.to_bytes("pass", &mut buf).unwrap();
+        let parsed = StunMessage::parse(&buf[..n]).unwrap();
+
+        assert_eq!(parsed.method(), Method::Binding);
+        assert_eq!(parsed.class(), Class::Failure);
+        assert_eq!(parsed.trans_id(), trans_id);
+        assert_eq!(parsed.error_code(), Some((487, "Role Conflict")));
+        assert!(parsed.check_integrity("pass"));
+        assert!(!parsed.check_integrity("other"));
+
+        let msg = StunMessage::binding_error(400, "Bad", trans_id).unwrap();
+        let n = msg.to_bytes("pass", &mut buf).unwrap();
+        let parsed = StunMessage::parse(&buf[..n]).unwrap();
+        assert_eq!(parsed.error_code(), Some((400, "Bad")));
+
+        assert!(StunMessage::binding_error(200, "Ok", trans_id).is_err());
+        assert!(StunMessage::binding_error(700, "Huh", trans_id).is_err());
+
+        // The longest reason we can parse back.
+        let reason = "a".repeat(128);
+        let msg = StunMessage::binding_error(400, &reason, trans_id).unwrap();
+        let n = msg.to_bytes("pass", &mut buf).unwrap();
+        let parsed = StunMessage::parse(&buf[..n]).unwrap();
+        assert_eq!(parsed.error_code(), Some((400, reason.as_str())));
+
+        let reason = "a".repeat(129);
+        assert!(matches!(
+            StunMessage::binding_error(400, &reason, trans_id),
+            Err(StunError::InvalidErrorCode(_))
+        ));
+    }
+
+    #[test]
+    fn ice_role_round_trip() {
+        for controlling in [true, false] {
+            let mut buf = vec![0_u8; DATAGRAM_MTU];
+            let msg = StunMessage::binding_request(
+                "abcd:efgh",
+                TransId::new(),
+                controlling,
+                0x0123_4567_89ab_cdef,
+                1,
+                false,
+            );
+            let n = msg.to_bytes("pass", &mut buf).unwrap();
+
+            let parsed = StunMessage::parse(&buf[..n]).unwrap();
+            if controlling {
+                assert_eq!(parsed.ice_controlling(), Some(0x0123_4567_89ab_cdef));
+                assert_eq!(parsed.ice_controlled(), None);
+            } else {
+                assert_eq!(parsed.ice_controlling(), None);
+                assert_eq!(parsed.ice_controlled(), Some(0x0123_4567_89ab_cdef));
+            }
+        }
+    }
+
+    #[test]
+    fn split_username_edge_cases() {
+        let split = |username| {
+            Attributes {
+                username: Some(username),
+                ..Default::default()
+            }
+            .split_username()
+        };
+
+        assert_eq!(split("abc:def"), Some(("abc", "def")));
+        assert_eq!(split("abc:def:ghi"), Some(("abc", "def:ghi")));
+        assert_eq!(split("abcdef"), None);
+        assert_eq!(split("abc:"), None);
+        assert_eq!(split(":def"), None);
+        assert_eq!(split(":"), None);
+        assert_eq!(split(""), None);
+
+        let no_username = Attributes::default();
+        assert_eq!(no_username.split_username(), None);
     }
 
     #[test]
@@ -884,4 +1283,150 @@ mod test {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn parse_short_buffer() {
+        for len in 0..20 {
+            let mut buf = vec![0; len];
+            if len >= 8 {
+                buf[4..8].copy_from_slice(MAGIC);
+            }
+
+            assert!(StunMessage::parse(&buf).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_bad_magic_cookie() {
+        let mut buf = vec![0_u8; 20];
+        buf[1] = 0x01;
+
+        assert!(StunMessage::parse(&buf).is_err());
+    }
+
+    #[test]
+    fn parse_bad_attribute_length() {
+        // The FINGERPRINT attribute claims 8 bytes, but only 4 are left.
+        let mut buf = vec![0_u8; 28];
+        buf[1] = 0x01;
+        buf[3] = 8;
+        buf[4..8].copy_from_slice(MAGIC);
+        buf[20..22].copy_from_slice(&Attributes::FINGERPRINT.to_be_bytes());
+        buf[23] = 8;
+
+        assert!(StunMessage::parse(&buf).is_err());
+
+        // A zero length FINGERPRINT as the last attribute.
+        buf[23] = 0;
+
+        assert!(StunMessage::parse(&buf).is_err());
+    }
+
+    #[test]
+    fn parse_bad_fingerprint() {
+        let mut buf = vec![0_u8; DATAGRAM_MTU];
+        let msg = StunMessage::binding_request("abcd:efgh", TransId::new(), true, 0, 1, true);
+        let n = msg.to_bytes("pass", &mut buf).unwrap();
+        buf.truncate(n);
+
+        assert!(StunMessage::parse(&buf).is_ok());
+
+        // Flip a bit in the fingerprint value.
+        buf[n - 1] ^= 1;
+        assert!(StunMessage::parse(&buf).is_err());
+        buf[n - 1] ^= 1;
+
+        // Flip a bit in the transaction id, which the fingerprint covers.
+        buf[10] ^= 1;
+        assert!(StunMessage::parse(&buf).is_err());
+    }
+
+    #[test]
+    fn parse_random_bytes_does_not_panic() {
+        // Fixed seed so a failure can be reproduced.
+        let mut rng = fastrand::Rng::with_seed(0x5354_554e);
+        for _ in 0..10_000 {
+            let len = rng.usize(0..200);
+            let buf: Vec<u8> = (0..len).map(|_| rng.u8(..)).collect();
+            let _ = StunMessage::parse(&buf);
+        }
+    }
+
+    #[test]
+    fn parse_random_attributes_does_not_panic() {
+        // A valid header followed by random attributes, to get past the header checks.
+        let mut rng = fastrand::Rng::with_seed(0x5354_554e);
+        for _ in 0..10_000 {
+            let len = rng.usize(0..=45) * 4;
+            let mut buf = vec![0_u8; 20 + len];
+            buf[0] = rng.u8(0..2);
+            buf[1] = rng.u8(..);
+            buf[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+            buf[4..8].copy_from_slice(MAGIC);
+            for b in &mut buf[8..] {
+                *b = rng.u8(..);
+            }
+
+            // Make attribute types and lengths plausible.
+            let mut off = 20;
+            while off + 4 <= buf.len() {
+                let typ = match rng.u8(0..8) {
+                    0 => Attributes::USERNAME,
+                    1 => Attributes::MESSAGE_INTEGRITY,
+                    2 => Attributes::ERROR_CODE,
+                    3 => Attributes::XOR_MAPPED_ADDRESS,
+                    4 => Attributes::FINGERPRINT,
+                    5 => Attributes::PRIORITY,
+                    6 => Attributes::ICE_CONTROLLING,
+                    _ => rng.u16(..),
+                };
+                let attr_len = rng.u16(0..24);
+                buf[off..(off + 2)].copy_from_slice(&typ.to_be_bytes());
+                buf[(off + 2)..(off + 4)].copy_from_slice(&attr_len.to_be_bytes());
+                off += 4 + (attr_len as usize + 3) / 4 * 4;
+            }
+
+            let _ = StunMessage::parse(&buf);
+        }
+    }
+
+    #[test]
+    fn parse_mutated_message_does_not_panic() {
+        let mut buf = vec![0_u8; DATAGRAM_MTU];
+        let msg = StunMessage::binding_request("abcd:efgh", TransId::new(), true, 0, 1, true);
+        let n = msg.to_bytes("pass", &mut buf).unwrap();
+        buf.truncate(n);
+
+        let mut rng = fastrand::Rng::with_seed(0x5354_554e);
+
+        for _ in 0..10_000 {
+            let mut mutated = buf.clone();
+            for _ in 0..rng.usize(1..4) {
+                let i = rng.usize(0..mutated.len());
+                mutated[i] = rng.u8(..);
+            }
+            if let Ok(message) = StunMessage::parse(&mutated) {
+                let _ = message.check_integrity("pass");
+            }
+        }
+    }
+
+    #[test]
+    fn username_padding() {
+        let mut buf = vec![0_u8; DATAGRAM_MTU];
+
+        for username in ["ab:c", "ab:cd", "ab:cde", "ab:cdef", "abcd:efgh"] {
+            let msg = StunMessage::binding_request(username, TransId::new(), true, 0, 1, false);
+            let n = msg.to_bytes("pass", &mut buf).unwrap();
+
+            // Header, USERNAME padded to 4, ICE-CONTROLLING, PRIORITY, MESSAGE-INTEGRITY
+            // and FINGERPRINT.
+            let padded = (username.len() + 3) / 4 * 4;
+            assert_eq!(n, 20 + 4 + padded + 12 + 8 + 24 + 8, "{username}");
+
+            let parsed = StunMessage::parse(&buf[..n]).unwrap();
+            assert_eq!(parsed.attributes().count(), 5, "{username}");
+            assert!(parsed.check_integrity("pass"));
+        }
+    }
 }
@@ -616,7 +616,9 @@ pub use ice_::{Candidate, CandidateKind, IceConnectionState, IceCreds};
 #[doc(hidden)]
 pub mod ice {
     pub use crate::ice_::IceCreds;
-    pub use crate::ice_::{IceAgent, IceAgentEvent};
+    pub use crate::ice_::IceTransportPolicy;
+    pub use crate::ice_::PairId;
+    pub use crate::ice_::{IceAgent, IceAgentEvent, IceAgentStats, IceTimings};
     pub use crate::io::{StunMessage, StunPacket};
 }
 
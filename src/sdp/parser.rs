@@ -296,12 +296,12 @@ where
                 port,
                 _,
                 kind,
-                _,     // (" tcptype ", tcptype)
-                raddr, // (" raddr ", addr, " rport ", port)
-                _,     // (" generation ", generation)
-                _,     // (" network-id ", network_id)
-                ufrag, // (" ufrag ", ufrag)
-                _,     // ("network-cost", network_cost)
+                _,            // (" tcptype ", tcptype)
+                raddr,        // (" raddr ", addr, " rport ", port)
+                _,            // (" generation ", generation)
+                _,            // (" network-id ", network_id)
+                ufrag,        // (" ufrag ", ufrag)
+                network_cost, // (" network-cost ", network_cost)
             )| {
                 Candidate::parsed(
                     found,
@@ -312,6 +312,10 @@ where
                     kind,
                     raddr.map(|(_, addr, _, port)| SocketAddr::from((addr, port))),
                     ufrag.map(|(_, u)| u),
+                    // This is an extension, a value out of range is clamped and garbage ignored.
+                    network_cost.and_then(|(_, c)| {
+                        c.parse::<u64>().ok().map(|v| v.min(u16::MAX as u64) as u16)
+                    }),
                 )
             },
         )
@@ -1217,10 +1221,21 @@ mod test {
         let a = "a=candidate:1 1 udp 1845494015 198.51.100.100 11100 typ host ufrag abc\r\n";
         let (c, _) = candidate_attribute().parse(a).unwrap();
         assert_eq!(c.ufrag(), Some("abc"));
+        assert_eq!(c.network_cost(), 0);
 
         let a = "a=candidate:3684617590 1 udp 2122260223 10.217.229.219 50028 typ host generation 0 network-id 1 network-cost 900";
         let (c, _) = candidate_attribute().parse(a).unwrap();
         assert_eq!(c.addr(), "10.217.229.219:50028".parse().unwrap());
+        assert_eq!(c.network_cost(), 900);
+
+        let a = "a=candidate:1 1 udp 2122260223 10.0.0.1 5000 typ host network-cost 70000";
+        let (c, _) = candidate_attribute().parse(a).unwrap();
+        assert_eq!(c.network_cost(), u16::MAX);
+
+        let a = "a=candidate:1 1 udp 2122260223 10.0.0.1 5000 typ host network-cost abc";
+        let (c, _) = candidate_attribute().parse(a).unwrap();
+        assert_eq!(c.addr(), "10.0.0.1:5000".parse().unwrap());
+        assert_eq!(c.network_cost(), 0);
 
         let a = "a=candidate:387183333 1 udp 1686052607 113.185.55.72 31267 typ srflx raddr 10.217.229.219 rport 50028 generation 0 network-id 1 network-cost 900";
         let (c, _) = candidate_attribute().parse(a).unwrap();